@@ -0,0 +1,159 @@
+use cosmwasm_std::{StdError, StdResult};
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use crate::geohash::GeoLocationTime;
+use crate::msg::CompactCell;
+
+/// The only version of the binary format so far
+pub const BINARY_FORMAT_VERSION: u8 = 1;
+
+pub const COMPRESSION_NONE: u8 = 0;
+pub const COMPRESSION_DEFLATE: u8 = 1;
+pub const COMPRESSION_GZIP: u8 = 2;
+
+/// Upper limit for the size of a decompressed payload, so a small payload can not make us
+/// allocate an unbounded amount of memory
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Decode a binary import payload to our internal structure (geohash + time).
+///
+/// The payload starts with a 2 byte header: the format version, and the compression of the rest
+/// of the payload (none, raw deflate or gzip). The (decompressed) body holds the same data as
+/// `CompactCell`s, encoded as unsigned LEB128 varints:
+///
+/// ```text
+/// cell count
+/// for every cell: integer geohash, time count, delta-encoded times
+/// ```
+pub fn binary_data_points(payload: &[u8]) -> StdResult<Vec<GeoLocationTime>> {
+    if payload.len() < 2 {
+        return Err(StdError::generic_err(
+            "Binary payload is missing its header",
+        ));
+    }
+    if payload[0] != BINARY_FORMAT_VERSION {
+        return Err(StdError::generic_err(format!(
+            "Unsupported binary format version {}",
+            payload[0]
+        )));
+    }
+
+    let body = match payload[1] {
+        COMPRESSION_NONE => payload[2..].to_vec(),
+        COMPRESSION_DEFLATE => inflate(&payload[2..])?,
+        COMPRESSION_GZIP => inflate(gzip_body(&payload[2..])?)?,
+        other => {
+            return Err(StdError::generic_err(format!(
+                "Unsupported compression {}",
+                other
+            )))
+        }
+    };
+
+    let mut reader = VarintReader {
+        data: &body,
+        pos: 0,
+    };
+    let cells = reader.read()?;
+
+    let mut data_points: Vec<GeoLocationTime> = vec![];
+    for _ in 0..cells {
+        let geohash_int = reader.read()?;
+        let count = reader.read()?;
+        // don't trust the count for the allocation, it may be bogus
+        let mut times: Vec<u64> = vec![];
+        for _ in 0..count {
+            times.push(reader.read()?);
+        }
+
+        let cell = CompactCell {
+            geohash: None,
+            geohash_int: Some(geohash_int),
+            times,
+        };
+        data_points.extend(cell.data_points()?);
+    }
+
+    if reader.pos != body.len() {
+        return Err(StdError::generic_err(
+            "Binary payload has trailing data after the last cell",
+        ));
+    }
+
+    Ok(data_points)
+}
+
+fn inflate(data: &[u8]) -> StdResult<Vec<u8>> {
+    decompress_to_vec_with_limit(data, MAX_DECOMPRESSED_SIZE)
+        .map_err(|e| StdError::generic_err(format!("Failed to decompress payload: {:?}", e)))
+}
+
+/// Strip the gzip header and trailer (RFC 1952), leaving the raw deflate stream
+fn gzip_body(data: &[u8]) -> StdResult<&[u8]> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let invalid = || StdError::generic_err("Invalid gzip header");
+    if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 {
+        return Err(invalid());
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = *data.get(pos).ok_or_else(invalid)? as usize
+            | (*data.get(pos + 1).ok_or_else(invalid)? as usize) << 8;
+        pos += 2 + len;
+    }
+    for flag in &[FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data[pos.min(data.len())..]
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or_else(invalid)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    // the trailer holds the CRC32 and size of the uncompressed data
+    if pos + 8 > data.len() {
+        return Err(invalid());
+    }
+    Ok(&data[pos..data.len() - 8])
+}
+
+struct VarintReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> VarintReader<'a> {
+    /// Read a single unsigned LEB128 varint
+    fn read(&mut self) -> StdResult<u64> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| StdError::generic_err("Binary payload ended unexpectedly"))?;
+            self.pos += 1;
+
+            if shift >= 64 || (shift == 63 && byte & 0x7f > 1) {
+                return Err(StdError::generic_err(
+                    "Varint in binary payload is too long",
+                ));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+}
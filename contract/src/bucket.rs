@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use std::slice::Iter;
+use std::cmp::{max, min, Ordering};
+use std::collections::{HashMap, HashSet};
 
 use bincode2;
 use cosmwasm_std::{ReadonlyStorage, StdError, StdResult, Storage};
@@ -7,72 +7,470 @@ use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::geohash::{neighbors, GeoLocationTime};
-use crate::pointer::{Pointer, Pointers, ONE_DAY};
-
-use self::BucketName::*;
+use crate::geohash::{neighborhood, Position, SearchArea, PRECISION};
+use crate::msg::{InfectionStatus, MatchWindow};
+use crate::pointer::{bucket_count, Pointer, Pointers};
 
 pub static BUCKETS_KEY: &[u8] = b"buckets";
+pub static INDEX_SHARDS_KEY: &[u8] = b"index_shards";
+pub static CELL_COUNT_KEY: &[u8] = b"cell_count";
+
+/// cells are grouped into index shards by their first `SHARD_PRECISION` characters (~5km)
+pub const SHARD_PRECISION: usize = 5;
+/// the key of the list of shard prefixes. It is not a geohash character, so it can't be a prefix
+pub const SHARD_LIST: &str = "*";
 
-/// `DailyBucket` stores all the geolocation data for a single day. It is not aware of any limits
+/// `DailyBucket` stores all the geolocation data for a single day (or a shorter period, if the
+/// contract was configured with a smaller bucket span). It is not aware of any limits
 /// itself. That is handled by the `Pointer` struct, which we use to select the appropriate bucket
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+///
+/// Every geohash cell is stored under its own key (bucket name + geohash), so a query only has to
+/// read and decrypt the cells it actually touches, and an import only rewrites the cells it adds
+/// data to. Cells are lazy-loaded into `locations` the first time they are accessed.
+#[derive(Clone, Debug)]
 pub struct DailyBucket {
+    pub name: BucketId,
     // optionally - store by time->location. Ends up requiring much more storage, since time resolution
     // is higher than location resolution. Storing in a BTreeMap makes searching for time ranges easier.
     // pub locations: BTreeMap<u64, Locations>,
-    pub locations: HashMap<String, Times>,
+    pub locations: HashMap<String, Cell>,
+    /// cells that were modified since they were loaded, and have to be written back on `store`
+    modified: HashSet<String>,
+    /// cells that did not exist in storage when they were loaded, and have to be added to the index
+    unindexed: HashSet<String>,
+    /// the index shards (and the list of shards) we read to find the cells in a larger area
+    shards: HashMap<String, SortedIndex>,
+}
+
+/// The amount of cells in a bucket, so we can tell how much of it is left to clear without
+/// reading its whole index
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct CellCount(pub u32);
+
+impl CellCount {
+    pub fn store<S: Storage>(&self, store: &mut S, id: &BucketId) -> StdResult<()> {
+        let mut config_store = PrefixedStorage::new(CELL_COUNT_KEY, store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error packing cell count"))?;
+
+        config_store.set(&id.to_bytes(), &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(store: &S, id: &BucketId) -> StdResult<Self> {
+        let config_store = ReadonlyPrefixedStorage::new(CELL_COUNT_KEY, store);
+        if let Some(count) = config_store.get(&id.to_bytes()) {
+            let count: Self = bincode2::deserialize(&count)
+                .map_err(|_| StdError::generic_err("Error deserializing cell count"))?;
+            return Ok(count);
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn remove<S: Storage>(store: &mut S, id: &BucketId) {
+        let mut config_store = PrefixedStorage::new(CELL_COUNT_KEY, store);
+        config_store.remove(&id.to_bytes());
+    }
+}
+
+/// A sorted list of geohashes, to find all the geohashes that start with a prefix. Since we cannot
+/// iterate over the storage, every bucket keeps an index of its cells, which is split into shards
+/// of cells with the same first `SHARD_PRECISION` characters, and the prefixes of those shards are
+/// kept in another list (`SHARD_LIST`). Finding the cells in an area only reads the shards it
+/// overlaps, an import only rewrites the shards of the cells it adds, and clearing a bucket goes
+/// over it a shard at a time, so nothing has to read (or rewrite) the whole index
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SortedIndex(pub Vec<String>);
+
+impl SortedIndex {
+    pub fn store<S: Storage>(&self, store: &mut S, id: &BucketId, key: &str) -> StdResult<()> {
+        let id = id.to_bytes();
+        let mut shard_store = PrefixedStorage::multilevel(&[INDEX_SHARDS_KEY, &id], store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error packing index shard"))?;
+
+        shard_store.set(key.as_bytes(), &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(store: &S, id: &BucketId, key: &str) -> StdResult<Self> {
+        let id = id.to_bytes();
+        let shard_store = ReadonlyPrefixedStorage::multilevel(&[INDEX_SHARDS_KEY, &id], store);
+        if let Some(shard) = shard_store.get(key.as_bytes()) {
+            let shard: Self = bincode2::deserialize(&shard)
+                .map_err(|_| StdError::generic_err("Error deserializing index shard"))?;
+            return Ok(shard);
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn remove<S: Storage>(store: &mut S, id: &BucketId, key: &str) {
+        let id = id.to_bytes();
+        let mut shard_store = PrefixedStorage::multilevel(&[INDEX_SHARDS_KEY, &id], store);
+        shard_store.remove(key.as_bytes());
+    }
+
+    /// Insert a geohash in its sorted position. Returns false if it is already in the list
+    pub fn insert(&mut self, ghash: &str) -> bool {
+        match self.0.binary_search_by(|g| g.as_str().cmp(ghash)) {
+            Ok(_) => false,
+            Err(idx) => {
+                self.0.insert(idx, ghash.to_string());
+                true
+            }
+        }
+    }
+
+    /// All the geohashes that start with `prefix`. They are next to each other in the sorted list
+    pub fn with_prefix(&self, prefix: &str) -> &[String] {
+        let start = self
+            .0
+            .binary_search_by(|g| g.as_str().cmp(prefix))
+            .unwrap_or_else(|idx| idx);
+        let len = self.0[start..]
+            .iter()
+            .take_while(|g| g.starts_with(prefix))
+            .count();
+        &self.0[start..start + len]
+    }
 }
 
+/// Identifies a bucket in storage. Buckets are created dynamically as the retention window moves,
+/// and each one is identified by the start time of the period it covers, so identifiers are never
+/// reused for a different period
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Hash, Eq)]
-pub enum BucketName {
-    One,
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
-    Eight,
-    Nine,
-    Ten,
-    Eleven,
-    Twelve,
-    Thirteen,
-    Fourteen,
+pub struct BucketId(pub u64);
+
+impl BucketId {
+    pub fn to_bytes(&self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+}
+
+/// All the data stored for a single geohash cell in a bucket: single data points, and stays - periods
+/// of time that someone spent in the cell, which are stored as a single entry instead of a data point
+/// for every few minutes
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Cell {
+    pub times: Times,
+    pub stays: Stays,
+    /// the positions of data points, for contracts which store them. Sorted by time. Data points
+    /// that were stored without a position only have an entry in `times`
+    pub positions: Vec<(u64, Position)>,
+    /// the entries of `times` and `stays` which belong to suspected cases. Like `times`, suspected
+    /// data points have an entry for every suspected data point at that time, and suspected stays
+    /// are counted per period like the stays themselves. Everything else is confirmed
+    pub suspected_times: Times,
+    pub suspected_stays: Stays,
 }
 
-impl BucketName {
-    pub fn iterator() -> Iter<'static, BucketName> {
-        static DIRECTIONS: [BucketName; 14] = [
-            One, Two, Three, Four, Five, Six, Seven, Eight, Nine, Ten, Eleven, Twelve, Thirteen,
-            Fourteen,
-        ];
-        DIRECTIONS.iter()
+impl Cell {
+    /// The amount of entries (data points and stays) in the cell
+    pub fn len(&self) -> usize {
+        self.times.0.len() + self.stays.count()
+    }
+
+    pub fn insert_stay(&mut self, stay: Stay) {
+        self.stays.insert(stay);
+    }
+
+    /// Remove a single stay. Returns false if it is not in the cell
+    pub fn remove_stay(&mut self, stay: &Stay) -> bool {
+        self.stays.remove(stay)
+    }
+
+    /// Add a batch of positions, keeping the list sorted
+    pub fn merge_positions(&mut self, positions: Vec<(u64, Position)>) {
+        if positions.is_empty() {
+            return;
+        }
+        self.positions.extend(positions);
+        self.positions.sort_unstable();
+    }
+
+    /// Remove a single occurrence of a position. Returns false if it was not in the cell
+    pub fn remove_position(&mut self, time: u64, position: Position) -> bool {
+        if let Ok(idx) = self.positions.binary_search(&(time, position)) {
+            self.positions.remove(idx);
+            return true;
+        }
+
+        false
+    }
+
+    /// Set the infection status of a single data point, which must already be in `times`. Several
+    /// data points can have the same time, so suspected data points are counted per time, and a
+    /// time can't have more of them than `times` does. Returns false if nothing changed
+    pub fn set_time_status(&mut self, time: u64, status: InfectionStatus) -> bool {
+        match status {
+            InfectionStatus::Suspected
+                if self.suspected_times.range(time, time).len()
+                    >= self.times.range(time, time).len() =>
+            {
+                false
+            }
+            InfectionStatus::Suspected => {
+                self.suspected_times.insert(time);
+                true
+            }
+            InfectionStatus::Confirmed => self.suspected_times.remove(time),
+        }
+    }
+
+    /// Set the infection status of a single stay. Suspected stays are counted, so every stay that
+    /// was marked as suspected has to be marked as confirmed once. Returns false if nothing changed
+    pub fn set_stay_status(&mut self, stay: Stay, status: InfectionStatus) -> bool {
+        match status {
+            InfectionStatus::Suspected => {
+                self.suspected_stays.insert(stay);
+                true
+            }
+            InfectionStatus::Confirmed => self.suspected_stays.remove(&stay),
+        }
     }
+
+    /// The most severe infection status of the entries that match the window [from, to] (and
+    /// `near`), or None if nothing matches
+    pub fn matched_status(
+        &self,
+        from: u64,
+        to: u64,
+        near: Option<Proximity>,
+    ) -> Option<InfectionStatus> {
+        let times = self.times_near(from, to, near);
+        let stays = self.stays_in(from, to);
+        if times.is_empty() && stays.is_empty() {
+            return None;
+        }
+
+        // a matching entry which is not marked as suspected is confirmed, so a time is confirmed if
+        // it matched more data points than were marked as suspected, and the stays are confirmed
+        // if some moment in the window is covered by more stays than suspected stays
+        // (`times` is sorted, so counting from the first entry of a time counts all of them)
+        let confirmed = times.iter().enumerate().any(|(i, time)| {
+            let matched = times[i..].iter().take_while(|t| *t == time).count();
+            matched > self.suspected_times.range(*time, *time).len()
+        }) || self.stays.overlapping(from, to).any(|period| {
+            let (start, end) = (max(period.start, from), min(period.end, to));
+            !self.suspected_stays.covers(start, end, period.count)
+        });
+        if confirmed {
+            return Some(InfectionStatus::Confirmed);
+        }
+
+        Some(InfectionStatus::Suspected)
+    }
+
+    /// All the timestamps in the window [from, to] that are close enough to `near`. Timestamps that
+    /// were stored without a position can't be checked, so they always count as close enough
+    pub fn times_near(&self, from: u64, to: u64, near: Option<Proximity>) -> Vec<u64> {
+        let times = self.times.range(from, to);
+        let near = match near {
+            Some(near) => near,
+            None => return times.to_vec(),
+        };
+
+        let start = self
+            .positions
+            .binary_search_by(|(time, _)| match time < &from {
+                true => Ordering::Less,
+                false => Ordering::Greater,
+            })
+            .unwrap_or_else(|idx| idx);
+        let positions = &self.positions[start..];
+
+        // both lists are sorted by time, so we go over them together, one timestamp at a time
+        let mut found: Vec<u64> = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < times.len() {
+            let time = times[i];
+            let count = times[i..].iter().take_while(|t| **t == time).count();
+
+            let mut with_position = 0;
+            let mut close = false;
+            while j < positions.len() && positions[j].0 == time {
+                with_position += 1;
+                close |= positions[j].1.distance(&near.position) <= near.distance as f64;
+                j += 1;
+            }
+
+            if close || with_position < count {
+                found.extend_from_slice(&times[i..i + count]);
+            }
+            i += count;
+        }
+
+        found
+    }
+
+    /// All the (merged) stays which overlap with the window [from, to]
+    pub fn stays_in(&self, from: u64, to: u64) -> Vec<Stay> {
+        self.stays
+            .merged()
+            .into_iter()
+            .filter(|stay| stay.start <= to && stay.end >= from)
+            .collect()
+    }
+}
+
+/// A period of time which is covered by exactly `count` stays
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct MergedStay {
+    pub start: u64,
+    pub end: u64,
+    pub count: u32,
+}
+
+/// The stays of a single cell, stored as the amount of stays that cover every period of time.
+/// Stays are split where other stays start and end, so the entries never overlap, and entries
+/// next to each other which are covered by the same amount of stays are merged. The list stays
+/// short no matter how many imports add to it, and a stay can still be removed exactly, by
+/// taking it out of every entry it covers, whatever it was merged with
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Stays {
+    /// sorted by start time
+    pub periods: Vec<MergedStay>,
+    /// the amount of stays that were inserted
+    pub total: u32,
 }
 
-impl Into<&[u8]> for BucketName {
-    fn into(self) -> &'static [u8] {
-        match self {
-            One => b"One",
-            Two => b"Two",
-            Three => b"Three",
-            Four => b"Four",
-            Five => b"Five",
-            Six => b"Six",
-            Seven => b"Seven",
-            Eight => b"Eight",
-            Nine => b"Nine",
-            Ten => b"Ten",
-            Eleven => b"Eleven",
-            Twelve => b"Twelve",
-            Thirteen => b"Thirteen",
-            Fourteen => b"Fourteen",
+impl Stays {
+    /// Add a stay to every period it covers
+    pub fn insert(&mut self, stay: Stay) {
+        self.update(&stay, true);
+        self.total += 1;
+    }
+
+    /// Remove a stay that was inserted before from every period it covers. Returns false if some
+    /// of its time is not covered by any stay, in which case nothing is changed
+    pub fn remove(&mut self, stay: &Stay) -> bool {
+        if !self.update(stay, false) {
+            return false;
         }
+        self.total -= 1;
+
+        true
     }
+
+    /// Add (or remove) a single stay, and split or merge the periods around it
+    fn update(&mut self, stay: &Stay, insert: bool) -> bool {
+        // every period we end up with starts at one of these, and ends right before the next one
+        let mut bounds: Vec<u64> = vec![stay.start, stay.end.saturating_add(1)];
+        for period in &self.periods {
+            bounds.push(period.start);
+            bounds.push(period.end.saturating_add(1));
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut periods: Vec<MergedStay> = Vec::with_capacity(bounds.len());
+        let mut idx = 0;
+        for pair in bounds.windows(2) {
+            let (from, to) = (pair[0], pair[1] - 1);
+            while idx < self.periods.len() && self.periods[idx].end < from {
+                idx += 1;
+            }
+            let mut count = match self.periods.get(idx) {
+                Some(period) if period.start <= from => period.count,
+                _ => 0,
+            };
+
+            if from >= stay.start && to <= stay.end {
+                match insert {
+                    true => count += 1,
+                    false if count == 0 => return false,
+                    false => count -= 1,
+                }
+            }
+            if count == 0 {
+                continue;
+            }
+
+            match periods.last_mut() {
+                Some(last) if last.count == count && last.end.saturating_add(1) == from => {
+                    last.end = to
+                }
+                _ => periods.push(MergedStay {
+                    start: from,
+                    end: to,
+                    count,
+                }),
+            }
+        }
+
+        self.periods = periods;
+        true
+    }
+
+    /// The amount of stays that were inserted
+    pub fn count(&self) -> usize {
+        self.total as usize
+    }
+
+    /// All the entries which overlap with the window [from, to]
+    pub fn overlapping(&self, from: u64, to: u64) -> impl Iterator<Item = &MergedStay> {
+        // the lists are short, so there is no need for anything smarter than this
+        self.periods
+            .iter()
+            .take_while(move |s| s.start <= to)
+            .filter(move |s| s.end >= from)
+    }
+
+    /// Whether every moment of [from, to] is covered by at least `count` stays
+    pub fn covers(&self, from: u64, to: u64, count: u32) -> bool {
+        let mut next = from;
+        for period in self.overlapping(from, to) {
+            if period.start > next || period.count < count {
+                return false;
+            }
+            if period.end >= to {
+                return true;
+            }
+            next = period.end + 1;
+        }
+
+        false
+    }
+
+    /// The periods that are covered by any stay, with the stays that overlap (or touch) merged
+    pub fn merged(&self) -> Vec<Stay> {
+        let mut merged: Vec<Stay> = vec![];
+        for period in &self.periods {
+            match merged.last_mut() {
+                Some(last) if last.end.saturating_add(1) == period.start => last.end = period.end,
+                _ => merged.push(Stay {
+                    start: period.start,
+                    end: period.end,
+                }),
+            }
+        }
+
+        merged
+    }
+}
+
+/// Limits matches to stored data points within `distance` meters of `position`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Proximity {
+    pub position: Position,
+    pub distance: u32,
 }
 
+/// A period of time (inclusive) that someone spent in a single cell
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+pub struct Stay {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// The timestamps at which a specific geohash was seen. The list is always kept sorted, so we can
+/// find the timestamps in a time window with a binary search, instead of scanning the whole list
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Times(pub Vec<u64>);
 
@@ -84,64 +482,498 @@ impl Default for Times {
     }
 }
 
+impl Times {
+    /// Index of the first timestamp which is not smaller than `time`
+    fn lower_bound(&self, time: u64) -> usize {
+        self.0
+            .binary_search_by(|t| match t < &time {
+                true => Ordering::Less,
+                false => Ordering::Greater,
+            })
+            .unwrap_or_else(|idx| idx)
+    }
+
+    /// Insert a single timestamp in its sorted position. Imported data is usually ordered by time,
+    /// so in most cases this ends up being an append
+    pub fn insert(&mut self, time: u64) {
+        let idx = self.lower_bound(time.saturating_add(1));
+        self.0.insert(idx, time);
+    }
+
+    /// Remove a single occurrence of `time` from the list. Returns false if it was not in the list
+    pub fn remove(&mut self, time: u64) -> bool {
+        let idx = self.lower_bound(time);
+        if self.0.get(idx) == Some(&time) {
+            self.0.remove(idx);
+            return true;
+        }
+
+        false
+    }
+
+    /// Merge a batch of timestamps into the list, keeping it sorted
+    pub fn merge(&mut self, mut times: Vec<u64>) {
+        times.sort_unstable();
+
+        let mut merged = Vec::with_capacity(self.0.len() + times.len());
+        let (mut old, mut new) = (self.0.iter().peekable(), times.into_iter().peekable());
+        loop {
+            let next = match (old.peek(), new.peek()) {
+                (Some(a), Some(b)) if *a <= b => *old.next().unwrap(),
+                (_, Some(_)) => new.next().unwrap(),
+                (Some(_), None) => *old.next().unwrap(),
+                (None, None) => break,
+            };
+            merged.push(next);
+        }
+
+        self.0 = merged;
+    }
+
+    /// All the timestamps between `from` and `to` (inclusive)
+    pub fn range(&self, from: u64, to: u64) -> &[u64] {
+        if from > to {
+            return &[];
+        }
+        let start = self.lower_bound(from);
+        let end = self.lower_bound(to.saturating_add(1));
+        &self.0[start..end]
+    }
+}
+
 impl DailyBucket {
-    pub fn store<S: Storage>(&self, store: &mut S, id: &BucketName) -> StdResult<()> {
-        let mut config_store = PrefixedStorage::new(BUCKETS_KEY, store);
-        let as_bytes = bincode2::serialize(&self)
-            .map_err(|_| StdError::generic_err("Error packing pointers"))?;
+    pub fn new(name: BucketId) -> Self {
+        Self {
+            name,
+            locations: Default::default(),
+            modified: Default::default(),
+            unindexed: Default::default(),
+            shards: Default::default(),
+        }
+    }
+
+    /// Write all the cells that were modified back to storage, and add any new cells to the
+    /// index shards of the bucket
+    pub fn store<S: Storage>(&self, store: &mut S) -> StdResult<()> {
+        {
+            let id = self.name.to_bytes();
+            let mut cell_store = PrefixedStorage::multilevel(&[BUCKETS_KEY, &id], store);
+            for ghash in &self.modified {
+                let as_bytes = bincode2::serialize(&self.locations[ghash])
+                    .map_err(|_| StdError::generic_err("Error packing bucket cell"))?;
+
+                cell_store.set(ghash.as_bytes(), &as_bytes);
+            }
+        }
+
+        // cells we only read (e.g. while matching) are empty, and don't need to be indexed
+        let new_cells: Vec<&String> = self.unindexed.intersection(&self.modified).collect();
+        if new_cells.is_empty() {
+            return Ok(());
+        }
+
+        let count = CellCount::load(store, &self.name)?.0 + new_cells.len() as u32;
+        CellCount(count).store(store, &self.name)?;
+
+        let mut shards: HashMap<&str, SortedIndex> = HashMap::default();
+        for ghash in &new_cells {
+            let prefix = &ghash[..SHARD_PRECISION];
+            if !shards.contains_key(prefix) {
+                shards.insert(prefix, SortedIndex::load(store, &self.name, prefix)?);
+            }
+            shards.get_mut(prefix).unwrap().insert(ghash);
+        }
+
+        let mut shard_list = SortedIndex::load(store, &self.name, SHARD_LIST)?;
+        let mut new_shards = false;
+        for (prefix, shard) in &shards {
+            shard.store(store, &self.name, prefix)?;
+            new_shards |= shard_list.insert(prefix);
+        }
+        if new_shards {
+            shard_list.store(store, &self.name, SHARD_LIST)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a single cell directly from storage
+    pub fn load_cell<S: ReadonlyStorage>(
+        store: &S,
+        id: &BucketId,
+        ghash: &str,
+    ) -> StdResult<Option<Cell>> {
+        let id = id.to_bytes();
+        let cell_store = ReadonlyPrefixedStorage::multilevel(&[BUCKETS_KEY, &id], store);
+        if let Some(cell) = cell_store.get(ghash.as_bytes()) {
+            let cell: Cell = bincode2::deserialize(&cell)
+                .map_err(|_| StdError::generic_err("Error deserializing bucket cell"))?;
+            return Ok(Some(cell));
+        }
+
+        Ok(None)
+    }
 
-        config_store.set((*id).into(), &as_bytes);
+    /// Remove a single cell from storage. This does not update the index shards
+    pub fn remove_cell<S: Storage>(store: &mut S, id: &BucketId, ghash: &str) {
+        let id = id.to_bytes();
+        let mut cell_store = PrefixedStorage::multilevel(&[BUCKETS_KEY, &id], store);
+        cell_store.remove(ghash.as_bytes());
+    }
+
+    /// Make sure a cell is loaded into `locations`, reading it from storage if this is the first
+    /// time we access it
+    fn load_to_cache<S: ReadonlyStorage>(&mut self, store: &S, ghash: &str) -> StdResult<()> {
+        if !self.locations.contains_key(ghash) {
+            let cell = match Self::load_cell(store, &self.name, ghash)? {
+                Some(cell) => cell,
+                None => {
+                    self.unindexed.insert(ghash.to_string());
+                    Cell::default()
+                }
+            };
+            self.locations.insert(ghash.to_string(), cell);
+        }
 
         Ok(())
     }
 
-    pub fn load<S: Storage>(store: &S, id: &BucketName) -> StdResult<Self> {
-        let config_store = ReadonlyPrefixedStorage::new(BUCKETS_KEY, store);
-        if let Some(bucket) = config_store.get((*id).into()) {
-            let ptrs: Self = bincode2::deserialize(&bucket)
-                .map_err(|_| StdError::generic_err("Error deserializing bucket"))?;
-            return Ok(ptrs);
+    /// Insert a batch of data points into a single cell. The timestamps are sorted once and merged
+    /// into the sorted list of the cell, so a large import costs a single pass over the cell,
+    /// instead of shifting the list for every data point. If the data points belong to a
+    /// suspected case, they are marked as suspected as well
+    pub fn insert_data_points<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        times: Vec<u64>,
+        positions: Vec<(u64, Position)>,
+        status: InfectionStatus,
+    ) -> StdResult<()> {
+        self.load_to_cache(store, ghash)?;
+
+        let entry = self.locations.get_mut(ghash).unwrap();
+        if status == InfectionStatus::Suspected {
+            entry.suspected_times.merge(times.clone());
         }
+        entry.times.merge(times);
+        entry.merge_positions(positions);
+        self.modified.insert(ghash.to_string());
 
-        Ok(Self {
-            locations: Default::default(),
-        })
+        Ok(())
     }
 
-    pub fn insert_data_point(&mut self, geotime: GeoLocationTime) {
-        let entry = self.locations.entry(geotime.geohash.clone()).or_default();
-        entry.0.push(geotime.timestamp_ms);
+    pub fn insert_stay<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        stay: Stay,
+    ) -> StdResult<()> {
+        self.load_to_cache(store, ghash)?;
+
+        self.locations.get_mut(ghash).unwrap().insert_stay(stay);
+        self.modified.insert(ghash.to_string());
+
+        Ok(())
+    }
+
+    /// Remove a single data point from a cell. Returns false if the cell has no data point at `time`
+    pub fn remove_data_point<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        time: u64,
+    ) -> StdResult<bool> {
+        self.load_to_cache(store, ghash)?;
+
+        let removed = self.locations.get_mut(ghash).unwrap().times.remove(time);
+        if removed {
+            self.modified.insert(ghash.to_string());
+        }
+
+        Ok(removed)
     }
 
-    fn _does_time_overlap(&self, ghash: &String, time: u64, period: u64) -> bool {
-        if let Some(times) = self.locations.get(ghash) {
+    /// Remove the position of a single data point from a cell. Returns false if the cell does not
+    /// have this position
+    pub fn remove_position<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        time: u64,
+        position: Position,
+    ) -> StdResult<bool> {
+        self.load_to_cache(store, ghash)?;
+
+        let removed = self
+            .locations
+            .get_mut(ghash)
+            .unwrap()
+            .remove_position(time, position);
+        if removed {
+            self.modified.insert(ghash.to_string());
+        }
+
+        Ok(removed)
+    }
+
+    /// Set the infection status of a single data point in a cell
+    pub fn set_time_status<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        time: u64,
+        status: InfectionStatus,
+    ) -> StdResult<()> {
+        self.load_to_cache(store, ghash)?;
+
+        if self
+            .locations
+            .get_mut(ghash)
+            .unwrap()
+            .set_time_status(time, status)
+        {
+            self.modified.insert(ghash.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Set the infection status of a single stay in a cell
+    pub fn set_stay_status<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        stay: Stay,
+        status: InfectionStatus,
+    ) -> StdResult<()> {
+        self.load_to_cache(store, ghash)?;
+
+        if self
+            .locations
+            .get_mut(ghash)
+            .unwrap()
+            .set_stay_status(stay, status)
+        {
+            self.modified.insert(ghash.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Remove a single stay from a cell. Returns false if the cell does not have this stay
+    pub fn remove_stay<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        stay: &Stay,
+    ) -> StdResult<bool> {
+        self.load_to_cache(store, ghash)?;
+
+        let removed = self.locations.get_mut(ghash).unwrap().remove_stay(stay);
+        if removed {
+            self.modified.insert(ghash.to_string());
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns the timestamps stored for a cell in the window [from, to], which are close enough
+    /// to `near`
+    fn _overlapping_times<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        from: u64,
+        to: u64,
+        near: Option<Proximity>,
+    ) -> StdResult<Vec<u64>> {
+        self.load_to_cache(store, ghash)?;
+
+        Ok(self.locations[ghash].times_near(from, to, near))
+    }
+
+    /// Returns the stays stored for a cell which overlap with the window [from, to]
+    fn _overlapping_stays<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        from: u64,
+        to: u64,
+    ) -> StdResult<Vec<Stay>> {
+        self.load_to_cache(store, ghash)?;
+
+        Ok(self.locations[ghash].stays_in(from, to))
+    }
+
+    /// Read an index shard (or the list of shards) the first time we need it
+    fn load_shard<S: ReadonlyStorage>(&mut self, store: &S, key: &str) -> StdResult<&SortedIndex> {
+        if !self.shards.contains_key(key) {
+            let shard = SortedIndex::load(store, &self.name, key)?;
+            self.shards.insert(key.to_string(), shard);
+        }
+
+        Ok(&self.shards[key])
+    }
+
+    /// The cells in the area of `pos`. A full precision geohash is a cell itself, the cells of
+    /// shorter geohashes are looked up in the index shards
+    fn cells_in<S: ReadonlyStorage>(&mut self, store: &S, pos: &str) -> StdResult<Vec<String>> {
+        if pos.len() >= PRECISION {
+            return Ok(vec![pos.to_string()]);
+        }
+
+        if pos.len() >= SHARD_PRECISION {
+            let shard = self.load_shard(store, &pos[..SHARD_PRECISION])?;
+            return Ok(shard.with_prefix(pos).to_vec());
+        }
+
+        // areas larger than a shard read every shard in them
+        let prefixes = self
+            .load_shard(store, SHARD_LIST)?
+            .with_prefix(pos)
+            .to_vec();
+        let mut cells: Vec<String> = vec![];
+        for prefix in prefixes {
+            cells.extend_from_slice(&self.load_shard(store, &prefix)?.0);
+        }
+
+        Ok(cells)
+    }
+
+    fn _does_time_overlap<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        pos: &str,
+        from: u64,
+        to: u64,
+        near: Option<Proximity>,
+    ) -> StdResult<bool> {
+        for ghash in self.cells_in(store, pos)? {
             // if we have data points for this location, check if the time overlaps, as well
-            for t in &times.0 {
-                if &time >= t && time <= t + period {
-                    // if match, no need to look any further
-                    return true;
+            if !self
+                ._overlapping_times(store, &ghash, from, to, near)?
+                .is_empty()
+            {
+                return Ok(true);
+            }
+
+            if !self._overlapping_stays(store, &ghash, from, to)?.is_empty() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Like `match_pos`, but returns all the stored timestamps and stays that overlap with the
+    /// input, from the whole search area, instead of stopping at the first match
+    pub fn overlapping_times<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        area: &SearchArea,
+        time: u64,
+        window: &MatchWindow,
+        near: Option<Proximity>,
+    ) -> StdResult<(Vec<u64>, Vec<Stay>)> {
+        let center = &ghash[..area.precision];
+        let mut positions = neighborhood(center, area.rings)?;
+        positions.push(center.to_string());
+
+        let (from, to) = window.bounds(time, time);
+        let mut times = Times::default();
+        let mut stays: Vec<Stay> = vec![];
+        for pos in positions {
+            for ghash in self.cells_in(store, &pos)? {
+                let found = self._overlapping_times(store, &ghash, from, to, near)?;
+                times.merge(found);
+                stays.extend(self._overlapping_stays(store, &ghash, from, to)?);
+            }
+        }
+        stays.sort_unstable();
+
+        Ok((times.0, stays))
+    }
+
+    /// Checks whether anything was stored in the search area around `ghash` within `window` of
+    /// `time`. The area can use shorter geohashes than the stored ones, to search a larger area. If
+    /// `near` is set, stored data points with a position must be close enough to it as well
+    pub fn match_pos<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        area: &SearchArea,
+        time: u64,
+        window: &MatchWindow,
+        near: Option<Proximity>,
+    ) -> StdResult<bool> {
+        self.match_interval(store, ghash, area, time, time, window, near)
+    }
+
+    /// Like `match_pos`, but returns the most severe infection status of all the matching
+    /// entries in the search area, or None if nothing matches
+    pub fn match_status<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        area: &SearchArea,
+        time: u64,
+        window: &MatchWindow,
+        near: Option<Proximity>,
+    ) -> StdResult<Option<InfectionStatus>> {
+        let (from, to) = window.bounds(time, time);
+        let center = &ghash[..area.precision];
+        let mut positions = vec![center.to_string()];
+        positions.extend(neighborhood(center, area.rings)?);
+
+        let mut status: Option<InfectionStatus> = None;
+        for pos in positions {
+            for ghash in self.cells_in(store, &pos)? {
+                self.load_to_cache(store, &ghash)?;
+
+                let found = self.locations[&ghash].matched_status(from, to, near);
+                status = status.max(found);
+                if status == Some(InfectionStatus::Confirmed) {
+                    return Ok(status);
                 }
             }
         }
-        return false;
+
+        Ok(status)
     }
 
-    pub fn match_pos(&self, ghash: &String, time: u64, period: u64) -> StdResult<bool> {
+    /// Checks whether anything was stored in the search area around `ghash` within `window` of
+    /// the interval [start, end]. A data point is the same as an interval which starts and ends at
+    /// the same time, so this is a generalization of `match_pos`
+    pub fn match_interval<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        ghash: &str,
+        area: &SearchArea,
+        start: u64,
+        end: u64,
+        window: &MatchWindow,
+        near: Option<Proximity>,
+    ) -> StdResult<bool> {
+        let (from, to) = window.bounds(start, end);
+        let center = &ghash[..area.precision];
+
         // test our initial data point
-        if self._does_time_overlap(ghash, time, period) {
+        if self._does_time_overlap(store, center, from, to, near)? {
             return Ok(true);
         }
 
-        // find all geohash neighbors - possible optimizations:
+        // find all the cells around it - possible optimizations:
         //     use integer geohashes
         //     a more optimized geohash curve
-        //     for even more accuracy haversine distance can be used, but that requires storing
-        // coordinates as well
-        let positions = neighbors(ghash)?;
+        //     for even more accuracy haversine distance is used when `near` is set, but only for
+        // data points that were stored with their coordinates
+        let positions = neighborhood(center, area.rings)?;
 
-        // test all the neighbors of our geohash (since overlap may also be on the limits of the hash)
+        // test all the cells around our geohash (since overlap may also be on the limits of the hash)
         for pos in positions {
-            if self._does_time_overlap(&pos, time, period) {
+            if self._does_time_overlap(store, &pos, from, to, near)? {
                 return Ok(true);
             }
         }
@@ -149,32 +981,19 @@ impl DailyBucket {
     }
 }
 
-/// Load all our buckets at once for convenience when we know we will most likely need all of
-/// them
-pub fn load_all_buckets<S: Storage>(store: &S) -> StdResult<HashMap<BucketName, DailyBucket>> {
-    let mut map = HashMap::<BucketName, DailyBucket>::default();
-    for name in BucketName::iterator() {
-        map.insert(name.clone(), DailyBucket::load(store, name)?);
-    }
-
-    Ok(map)
-}
-
 /// Initialize our buckets, according to a specific start time (time since epoch in milliseconds),
 /// which will be the earliest allowed timestamp for data in our buckets.
-/// The last allowed timestamp will be 14 * `ONE_DAY`
-pub fn initialize_buckets<S: Storage>(store: &mut S, start_time: u64) -> StdResult<()> {
-    let mut cur_time = start_time;
+/// The last allowed timestamp will be `retention_days` * `ONE_DAY`, split into buckets which each
+/// cover `bucket_span` milliseconds
+pub fn initialize_buckets<S: Storage>(
+    store: &mut S,
+    start_time: u64,
+    retention_days: u32,
+    bucket_span: u64,
+) -> StdResult<()> {
     let mut pointers = Pointers::default();
-    for name in BucketName::iterator() {
-        let new_pointer = Pointer {
-            start_time: cur_time,
-            end_time: cur_time + ONE_DAY,
-            bucket: name.clone(),
-        };
-        cur_time = cur_time + ONE_DAY + 1;
-
-        pointers.insert(new_pointer);
+    for i in 0..bucket_count(retention_days, bucket_span) as u64 {
+        pointers.insert(Pointer::new(start_time + i * bucket_span, bucket_span));
     }
     pointers.store(store)
 }
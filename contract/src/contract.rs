@@ -1,12 +1,24 @@
 use crate::bucket::initialize_buckets;
-use crate::data::{import_location_data, match_data_point};
+use crate::contributor::{delete_contributor, set_contributor_status};
+use crate::data::{
+    import_binary_data, import_compact_data, import_geojson_data, import_gpx_data,
+    import_location_data, import_semantic_data, match_data_point, match_data_point_times,
+    match_intervals, query_data_points, query_distance, query_match_window, query_search_radius,
+};
+use crate::expiry::{process_expiry, query_expiry_status};
+use crate::exposure::exposure_summary;
 use crate::hotspotmap::HotSpots;
-use crate::msg::{HandleMsg, InitMsg, QueryAnswer, QueryMsg};
+use crate::msg::{HandleMsg, InitMsg, MatchLimits, MatchWindow, QueryAnswer, QueryMsg};
+use crate::pointer::{DEFAULT_RETENTION_DAYS, ONE_HOUR};
+use crate::risk::{risk_score, set_risk_config};
+use crate::session::{abort_import, begin_import, commit_import, import_chunk};
 use crate::state::{config, config_read, State};
-use crate::time::{new_day, query_dates};
+use crate::time::{
+    new_day, query_dates, rotate_buckets, set_retention, validate_bucket_hours, validate_retention,
+};
 use cosmwasm_std::{
-    to_binary, Api, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier, QueryResult,
-    StdError, StdResult, Storage,
+    log, to_binary, Api, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier,
+    QueryResult, StdError, StdResult, Storage,
 };
 
 /// Initialize the contract with the start time, and the contract administrator
@@ -15,14 +27,38 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
+    let retention_days = msg.retention_days.unwrap_or(DEFAULT_RETENTION_DAYS);
+    validate_retention(retention_days)?;
+
+    let bucket_hours = msg.bucket_hours.unwrap_or(24);
+    validate_bucket_hours(bucket_hours)?;
+
+    if let Some(window) = &msg.match_window {
+        window.validate()?;
+    }
+    if let Some(limits) = &msg.match_limits {
+        limits.validate()?;
+    }
+
     let state = State {
         admin: vec![env.message.sender],
+        bucket_span: bucket_hours as u64 * ONE_HOUR,
+        max_accuracy: msg.max_accuracy,
+        match_window: msg.match_window,
+        match_limits: msg.match_limits,
+        match_distance: msg.match_distance,
+        risk_config: None,
     };
 
     config(&mut deps.storage).save(&state)?;
 
     // initialize data buckets
-    initialize_buckets(&mut deps.storage, msg.start_time)?;
+    initialize_buckets(
+        &mut deps.storage,
+        msg.start_time,
+        retention_days,
+        state.bucket_span,
+    )?;
 
     Ok(InitResponse::default())
 }
@@ -32,9 +68,16 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
+    // move our window of buckets forward once the block time passes the newest bucket. This is
+    // checked on every handle, so old data expires even if nobody sends `ChangeDay`
+    let rotated = rotate_buckets(&mut deps.storage, env.block.time * 1000)?;
+
+    // anyone can send these - they only do maintenance work which is already due
+    let permissionless = matches!(msg, HandleMsg::Tick {} | HandleMsg::ProcessExpiry { .. });
+
     let state = config_read(&deps.storage).load()?;
 
-    if !state.admin.contains(&env.message.sender) {
+    if !permissionless && !state.admin.contains(&env.message.sender) {
         return Err(StdError::generic_err(
             "You cannot functions from non-admin address".to_string(),
         ));
@@ -46,18 +89,121 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
         HandleMsg::AddAdmin { address } => add_admin(deps, env, address),
         // remove an admin
         HandleMsg::RemoveAdmin { address } => remove_admin(deps, env, address),
+        // rotate buckets according to the block time
+        HandleMsg::Tick {} => tick(rotated),
+        // clear some of the expired data
+        HandleMsg::ProcessExpiry { limit } => process_expiry(deps, env, limit),
         // signal that a day has passed
-        HandleMsg::ChangeDay {} => new_day(deps, env),
+        HandleMsg::ChangeDay { time } => new_day(deps, env, time),
+        // change the amount of days for which data is kept
+        HandleMsg::SetRetention { days } => set_retention(deps, env, days),
         // import new geolocation data
-        HandleMsg::ImportGoogleLocations { data } => import_location_data(deps, env, data),
+        HandleMsg::ImportGoogleLocations {
+            data,
+            contributor,
+            stay_points,
+        } => import_location_data(deps, env, data, contributor, stay_points),
+        HandleMsg::ImportSemanticLocations { data, contributor } => {
+            import_semantic_data(deps, env, data, contributor)
+        }
+        HandleMsg::ImportGpx { data, contributor } => import_gpx_data(deps, env, data, contributor),
+        HandleMsg::ImportGeoJson { data, contributor } => {
+            import_geojson_data(deps, env, data, contributor)
+        }
+        HandleMsg::ImportCompact { cells, contributor } => {
+            import_compact_data(deps, env, cells, contributor)
+        }
+        HandleMsg::ImportBinary { data, contributor } => {
+            import_binary_data(deps, env, data, contributor)
+        }
+        // import new geolocation data over multiple transactions
+        HandleMsg::BeginImport { contributor } => begin_import(deps, env, contributor),
+        HandleMsg::ImportChunk { session_id, data } => import_chunk(deps, env, session_id, data),
+        HandleMsg::CommitImport { session_id } => commit_import(deps, env, session_id),
+        HandleMsg::AbortImport { session_id } => abort_import(deps, env, session_id),
+        HandleMsg::SetMaxAccuracy { max_accuracy } => set_max_accuracy(deps, env, max_accuracy),
+        HandleMsg::SetMatchWindow { match_window } => set_match_window(deps, env, match_window),
+        HandleMsg::SetMatchLimits { match_limits } => set_match_limits(deps, env, match_limits),
+        HandleMsg::SetMatchDistance { match_distance } => {
+            set_match_distance(deps, env, match_distance)
+        }
+        HandleMsg::SetRiskConfig { risk_config } => set_risk_config(deps, env, risk_config),
+        HandleMsg::SetContributorStatus {
+            contributor,
+            status,
+        } => set_contributor_status(deps, env, contributor, status),
+        // remove the data of a single contributor
+        HandleMsg::DeleteContributor { contributor } => delete_contributor(deps, env, contributor),
     }
 }
 
 pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
     match msg {
-        QueryMsg::MatchDataPoints { data_points } => match_data_point(deps, data_points),
+        QueryMsg::MatchDataPoints {
+            data_points,
+            gpx,
+            geojson,
+            window,
+            radius,
+        } => match_data_point(
+            deps,
+            query_data_points(&deps.storage, data_points, gpx, geojson)?,
+            query_match_window(&deps.storage, window)?,
+            query_search_radius(&deps.storage, radius)?,
+            query_distance(&deps.storage, radius)?,
+        ),
+        QueryMsg::MatchTimes {
+            data_points,
+            gpx,
+            geojson,
+            window,
+            radius,
+        } => match_data_point_times(
+            deps,
+            query_data_points(&deps.storage, data_points, gpx, geojson)?,
+            query_match_window(&deps.storage, window)?,
+            query_search_radius(&deps.storage, radius)?,
+            query_distance(&deps.storage, radius)?,
+        ),
+        QueryMsg::ExposureSummary {
+            data_points,
+            gpx,
+            geojson,
+            window,
+            radius,
+        } => exposure_summary(
+            deps,
+            query_data_points(&deps.storage, data_points, gpx, geojson)?,
+            query_match_window(&deps.storage, window)?,
+            query_search_radius(&deps.storage, radius)?,
+            query_distance(&deps.storage, radius)?,
+        ),
+        QueryMsg::RiskScore {
+            data_points,
+            gpx,
+            geojson,
+            window,
+            time,
+        } => risk_score(
+            deps,
+            query_data_points(&deps.storage, data_points, gpx, geojson)?,
+            query_match_window(&deps.storage, window)?,
+            time,
+        ),
+        QueryMsg::MatchIntervals {
+            intervals,
+            window,
+            radius,
+        } => match_intervals(
+            deps,
+            intervals,
+            query_match_window(&deps.storage, window)?,
+            query_search_radius(&deps.storage, radius)?,
+            query_distance(&deps.storage, radius)?,
+        ),
         QueryMsg::HotSpot { accuracy, zones } => hotspots(deps, accuracy, zones),
         QueryMsg::TimeRange {} => query_dates(deps),
+        QueryMsg::ExpiryStatus {} => query_expiry_status(deps),
     }
 }
 
@@ -71,6 +217,14 @@ pub fn hotspots<S: Storage, A: Api, Q: Querier>(
     return to_binary(&QueryAnswer::HotSpotResponse { hot_spots: res.0 });
 }
 
+pub fn tick(rotated: u64) -> StdResult<HandleResponse> {
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("rotated_buckets", rotated)],
+        data: None,
+    })
+}
+
 pub fn add_admin<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     _env: Env,
@@ -86,6 +240,66 @@ pub fn add_admin<S: Storage, A: Api, Q: Querier>(
     Ok(HandleResponse::default())
 }
 
+pub fn set_max_accuracy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    max_accuracy: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let mut state = config(&mut deps.storage).load()?;
+
+    state.max_accuracy = max_accuracy;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse::default())
+}
+
+pub fn set_match_window<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    match_window: Option<MatchWindow>,
+) -> StdResult<HandleResponse> {
+    if let Some(window) = &match_window {
+        window.validate()?;
+    }
+
+    let mut state = config(&mut deps.storage).load()?;
+
+    state.match_window = match_window;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse::default())
+}
+
+pub fn set_match_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    match_limits: Option<MatchLimits>,
+) -> StdResult<HandleResponse> {
+    if let Some(limits) = &match_limits {
+        limits.validate()?;
+    }
+
+    let mut state = config(&mut deps.storage).load()?;
+
+    state.match_limits = match_limits;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse::default())
+}
+
+pub fn set_match_distance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    match_distance: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let mut state = config(&mut deps.storage).load()?;
+
+    state.match_distance = match_distance;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse::default())
+}
+
 pub fn remove_admin<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     _env: Env,
@@ -103,19 +317,37 @@ pub fn remove_admin<S: Storage, A: Api, Q: Querier>(
 
 #[cfg(test)]
 mod tests {
+    use std::convert::TryInto;
     use std::fs::File;
     use std::io::Read;
     use std::time::{Duration, Instant};
 
     use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
-    use cosmwasm_std::{Coin, Env, Extern, HumanAddr, InitResponse, MemoryStorage, StdResult};
+    use cosmwasm_std::{
+        from_binary, log, Binary, Coin, Env, Extern, HumanAddr, InitResponse, MemoryStorage,
+        StdError, StdResult, Uint128,
+    };
     use serde::{Deserialize, Serialize};
     use serde_json;
 
+    use crate::bucket::{
+        Cell, CellCount, DailyBucket, MergedStay, SortedIndex, Stay, SHARD_LIST, SHARD_PRECISION,
+    };
     use crate::contract::init;
+    use crate::contributor::{BucketContributors, Contribution};
     use crate::data::import_location_data;
+    use crate::expiry::ExpiryQueue;
+    use crate::geohash::{neighborhood, GeoLocationTime, SearchArea, MAX_RINGS};
+    use crate::hotspotmap::HotspotMap;
     use crate::msg::HandleMsg::ImportGoogleLocations;
-    use crate::msg::InitMsg;
+    use crate::msg::{
+        CompactCell, DailyExposure, DurationWeight, ExposureRisk, GoogleLocation,
+        GoogleTakeoutHistory, HandleAnswer, HandleMsg, InfectionStatus, InitMsg, LocationInterval,
+        MatchLimits, MatchWindow, ProximityTier, QueryAnswer, QueryMsg, RiskConfig, StayPoints,
+    };
+    use crate::pointer::{Pointers, ONE_DAY, ONE_HOUR};
+    use crate::session::{ImportSession, MAX_SESSION_CHUNKS, MAX_SESSION_POINTS, SESSION_TTL};
+    use crate::time::{parse_rfc3339, rotate_buckets};
 
     pub const MOCK_CONTRACT_ADDR: &str = "cosmos2contract";
 
@@ -141,11 +373,50 @@ mod tests {
 
         let init_msg = InitMsg {
             start_time: 1600129528955,
+            retention_days: None,
+            bucket_hours: None,
+            max_accuracy: None,
+            match_window: None,
+            match_limits: None,
+            match_distance: None,
         };
 
         (init(&mut deps, env.clone(), init_msg), deps, env)
     }
 
+    fn location(latitude: i64, longitude: i64, timestamp: u64) -> GoogleLocation {
+        GoogleLocation {
+            timestampMs: Some(Uint128::from(timestamp as u128)),
+            timestamp: None,
+            latitudeE7: latitude,
+            longitudeE7: longitude,
+            accuracy: None,
+            source: None,
+            deviceTag: None,
+        }
+    }
+
+    fn query_overlap(
+        deps: &Extern<MockStorage, MockApi, MockQuerier>,
+        data_points: Vec<GoogleLocation>,
+    ) -> Vec<GeoLocationTime> {
+        let res = crate::contract::query(
+            deps,
+            QueryMsg::MatchDataPoints {
+                data_points,
+                gpx: None,
+                geojson: None,
+                window: None,
+                radius: None,
+            },
+        )
+        .unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::Overlap { data_points } => data_points,
+            _ => panic!("unexpected query answer"),
+        }
+    }
+
     fn load_google_data() -> Vec<u8> {
         let mut cert = vec![];
         let mut f = File::open("tests/data/datamsg2.json").unwrap();
@@ -163,4 +434,1700 @@ mod tests {
         crate::contract::handle(&mut deps, env, data_msg);
         println!("elapsed: {}", now.elapsed().as_millis());
     }
+
+    #[test]
+    pub fn test_match_imported_cells() {
+        let (_, mut deps, env) = init_helper();
+
+        let first = location(525331150, 134378710, 1600693951455);
+        let second = location(525331150, 134378710, 1600694951455);
+        for dp in vec![first.clone(), second] {
+            let msg = ImportGoogleLocations {
+                data: GoogleTakeoutHistory {
+                    locations: vec![dp],
+                },
+                contributor: None,
+                stay_points: None,
+            };
+            crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        }
+
+        // both imports went into the same cell, which should only be indexed once
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        let bucket = pointers.find_bucket(1600693951455).unwrap();
+        assert_eq!(CellCount::load(&deps.storage, &bucket).unwrap().0, 1);
+        let ghash = first.hash().unwrap();
+        assert_eq!(
+            SortedIndex::load(&deps.storage, &bucket, &ghash[..SHARD_PRECISION])
+                .unwrap()
+                .0,
+            vec![ghash]
+        );
+
+        let overlap = query_overlap(&deps, vec![location(525331150, 134378710, 1600693981455)]);
+        assert_eq!(overlap.len(), 1);
+        assert_eq!(overlap[0].geohash, first.hash().unwrap());
+
+        let overlap = query_overlap(&deps, vec![location(525431150, 134378710, 1600693981455)]);
+        assert!(overlap.is_empty());
+    }
+
+    #[test]
+    pub fn test_match_times_window() {
+        let (_, mut deps, env) = init_helper();
+
+        // imported out of order, should be stored sorted
+        let times: Vec<u64> = vec![1600693951455, 1600693651455, 1600693851455, 1600690000000];
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: times
+                    .iter()
+                    .map(|t| location(525331150, 134378710, *t))
+                    .collect(),
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        // a later import is merged with the stored times
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![
+                    location(525331150, 134378710, 1600693901455),
+                    location(525331150, 134378710, 1600693751455),
+                ],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+
+        let msg = QueryMsg::MatchTimes {
+            data_points: vec![location(525331150, 134378710, 1600693951455)],
+            gpx: None,
+            geojson: None,
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, msg).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::OverlapTimes { matches } => {
+                assert_eq!(matches.len(), 1);
+                assert_eq!(
+                    matches[0].times,
+                    vec![
+                        1600693651455,
+                        1600693751455,
+                        1600693851455,
+                        1600693901455,
+                        1600693951455
+                    ]
+                );
+            }
+            _ => panic!("unexpected query answer"),
+        }
+    }
+
+    #[test]
+    pub fn test_set_retention() {
+        let (_, mut deps, env) = init_helper();
+        assert_eq!(Pointers::load(&deps.storage).unwrap().len(), 14);
+
+        let first_day = location(525331150, 134378710, 1600129529955);
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![first_day.clone()],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(query_overlap(&deps, vec![first_day.clone()]).len(), 1);
+
+        let msg = HandleMsg::SetRetention { days: 10 };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert_eq!(pointers.len(), 10);
+        assert!(pointers.find_bucket(1600129529955).is_none());
+        assert!(query_overlap(&deps, vec![first_day]).is_empty());
+
+        let msg = HandleMsg::SetRetention { days: 21 };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert_eq!(pointers.len(), 21);
+        assert!(pointers.find_bucket(1600129529955).is_some());
+
+        let msg = HandleMsg::SetRetention { days: 0 };
+        assert!(crate::contract::handle(&mut deps, env, msg).is_err());
+    }
+
+    #[test]
+    pub fn test_hourly_buckets() {
+        let mut deps = init_deps(20, &[]);
+        let env = mock_env("instantiator", &[]);
+        let init_msg = InitMsg {
+            start_time: 1600129528955,
+            retention_days: Some(10),
+            bucket_hours: Some(4),
+            max_accuracy: None,
+            match_window: None,
+            match_limits: None,
+            match_distance: None,
+        };
+        init(&mut deps, env.clone(), init_msg).unwrap();
+
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert_eq!(pointers.len(), 60);
+        assert_eq!(
+            pointers.last().unwrap().end_time,
+            1600129528955 + 10 * ONE_DAY - 1
+        );
+        assert_ne!(
+            pointers.find_bucket(1600129528955),
+            pointers.find_bucket(1600129528955 + 4 * ONE_HOUR)
+        );
+
+        let msg = HandleMsg::SetRetention { days: 14 };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(Pointers::load(&deps.storage).unwrap().len(), 84);
+
+        let init_msg = InitMsg {
+            start_time: 1600129528955,
+            retention_days: None,
+            bucket_hours: Some(5),
+            max_accuracy: None,
+            match_window: None,
+            match_limits: None,
+            match_distance: None,
+        };
+        assert!(init(&mut init_deps(20, &[]), env, init_msg).is_err());
+    }
+
+    #[test]
+    pub fn test_change_day_expires_oldest() {
+        let (_, mut deps, env) = init_helper();
+
+        let first_day = location(525331150, 134378710, 1600129529955);
+        let second_day = location(525431150, 134378710, 1600129529955 + ONE_DAY);
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![first_day.clone(), second_day.clone()],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(HotspotMap::load(&deps.storage).unwrap().locations.len(), 2);
+
+        let newest = Pointers::load(&deps.storage)
+            .unwrap()
+            .last()
+            .unwrap()
+            .clone();
+
+        crate::contract::handle(&mut deps, env.clone(), HandleMsg::ChangeDay { time: None })
+            .unwrap();
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert_eq!(pointers.len(), 14);
+        assert!(pointers.find_bucket(newest.end_time + ONE_DAY).is_some());
+        assert!(query_overlap(&deps, vec![first_day]).is_empty());
+        assert_eq!(query_overlap(&deps, vec![second_day.clone()]).len(), 1);
+        assert_eq!(HotspotMap::load(&deps.storage).unwrap().locations.len(), 1);
+
+        // catch up on a few missed days at once
+        let msg = HandleMsg::ChangeDay {
+            time: Some(newest.end_time + 3 * ONE_DAY),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert_eq!(pointers.len(), 14);
+        assert_eq!(
+            pointers.last().unwrap().end_time,
+            newest.end_time + 3 * ONE_DAY
+        );
+        assert!(query_overlap(&deps, vec![second_day]).is_empty());
+        assert!(HotspotMap::load(&deps.storage)
+            .unwrap()
+            .locations
+            .is_empty());
+
+        // skipping more than the whole window
+        let msg = HandleMsg::ChangeDay {
+            time: Some(newest.end_time + 100 * ONE_DAY),
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert_eq!(pointers.len(), 14);
+        assert_eq!(
+            pointers.first().unwrap().start_time,
+            newest.end_time + 86 * ONE_DAY + 1
+        );
+    }
+
+    #[test]
+    pub fn test_tick_rotates_by_block_time() {
+        let (_, mut deps, mut env) = init_helper();
+
+        let first_day = location(525331150, 134378710, 1600129529955);
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![first_day.clone()],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let newest = Pointers::load(&deps.storage)
+            .unwrap()
+            .last()
+            .unwrap()
+            .clone();
+
+        // anyone can tick
+        env.message.sender = HumanAddr::from("somebody");
+        env.block.time = (newest.end_time + ONE_DAY) / 1000;
+        crate::contract::handle(&mut deps, env.clone(), HandleMsg::Tick {}).unwrap();
+
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        assert!(pointers.find_bucket(env.block.time * 1000).is_some());
+        assert!(query_overlap(&deps, vec![first_day]).is_empty());
+
+        // but other handles are still only for admins
+        let msg = HandleMsg::SetRetention { days: 10 };
+        assert!(crate::contract::handle(&mut deps, env, msg).is_err());
+    }
+
+    #[test]
+    pub fn test_chunked_expiry() {
+        let (_, mut deps, env) = init_helper();
+
+        // 5 different cells on the first day
+        let first_day: Vec<GoogleLocation> = (0..5)
+            .map(|i| location(525331150 + i * 100000, 134378710, 1600129529955))
+            .collect();
+        let second_day = location(525331150, 135378710, 1600129529955 + ONE_DAY);
+        let mut locations = first_day.clone();
+        locations.push(second_day.clone());
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory { locations },
+            contributor: Some("contributor".to_string()),
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let expiry_status = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            let res = crate::contract::query(deps, QueryMsg::ExpiryStatus {}).unwrap();
+            match from_binary(&res).unwrap() {
+                QueryAnswer::ExpiryStatus {
+                    pending_buckets,
+                    processed_cells,
+                    remaining_cells,
+                } => (pending_buckets, processed_cells, remaining_cells),
+                _ => panic!("unexpected query answer"),
+            }
+        };
+
+        // rotate without clearing anything yet
+        let pointers = Pointers::load(&deps.storage).unwrap();
+        let expired = pointers.find_bucket(1600129529955).unwrap();
+        let kept = pointers.find_bucket(1600129529955 + ONE_DAY).unwrap();
+        assert_eq!(
+            Contribution::load(&deps.storage, "contributor", &expired)
+                .unwrap()
+                .0
+                .len(),
+            5
+        );
+        let newest = pointers.last().unwrap().clone();
+        let mut shards: Vec<String> = first_day
+            .iter()
+            .map(|dp| dp.hash().unwrap()[..SHARD_PRECISION].to_string())
+            .collect();
+        shards.dedup();
+        assert_eq!(
+            SortedIndex::load(&deps.storage, &expired, SHARD_LIST)
+                .unwrap()
+                .0,
+            shards
+        );
+        rotate_buckets(&mut deps.storage, newest.end_time + 1).unwrap();
+        assert_eq!(expiry_status(&deps), (1, 0, 5));
+
+        // expired data is no longer matched, even before it is cleared
+        assert!(query_overlap(&deps, first_day.clone()).is_empty());
+        assert_eq!(query_overlap(&deps, vec![second_day.clone()]).len(), 1);
+
+        let msg = HandleMsg::ProcessExpiry { limit: Some(3) };
+        crate::contract::handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(expiry_status(&deps), (1, 3, 2));
+        assert_eq!(HotspotMap::load(&deps.storage).unwrap().locations.len(), 3);
+
+        // shards are removed as soon as all of their cells are cleared
+        let queue = ExpiryQueue::load(&deps.storage).unwrap();
+        let left: usize = shards
+            .iter()
+            .map(|shard| {
+                SortedIndex::load(&deps.storage, &expired, shard)
+                    .unwrap()
+                    .0
+                    .len()
+            })
+            .sum();
+        assert_eq!(left - queue.shard_cursor as usize, 2);
+
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(expiry_status(&deps), (0, 0, 0));
+        assert_eq!(HotspotMap::load(&deps.storage).unwrap().locations.len(), 1);
+
+        // the contributions to the bucket are removed with it
+        assert!(Contribution::load(&deps.storage, "contributor", &expired)
+            .unwrap()
+            .0
+            .is_empty());
+        assert!(BucketContributors::load(&deps.storage, &expired)
+            .unwrap()
+            .0
+            .is_empty());
+        assert_eq!(
+            Contribution::load(&deps.storage, "contributor", &kept)
+                .unwrap()
+                .0
+                .len(),
+            1
+        );
+
+        // the index shards are removed with the cells
+        for shard in shards {
+            assert!(SortedIndex::load(&deps.storage, &expired, &shard)
+                .unwrap()
+                .0
+                .is_empty());
+        }
+        assert!(SortedIndex::load(&deps.storage, &expired, SHARD_LIST)
+            .unwrap()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_import_session() {
+        let (_, mut deps, env) = init_helper();
+
+        let begin = |deps: &mut Extern<MockStorage, MockApi, MockQuerier>| {
+            let res = crate::contract::handle(
+                deps,
+                env.clone(),
+                HandleMsg::BeginImport { contributor: None },
+            );
+            match from_binary(&res.unwrap().data.unwrap()).unwrap() {
+                HandleAnswer::BeginImport { session_id } => session_id,
+            }
+        };
+
+        let first = location(525331150, 134378710, 1600693951455);
+        let second = location(525431150, 134378710, 1600693951455);
+
+        let session_id = begin(&mut deps);
+        for dp in vec![first.clone(), second.clone()] {
+            let msg = HandleMsg::ImportChunk {
+                session_id,
+                data: GoogleTakeoutHistory {
+                    locations: vec![dp],
+                },
+            };
+            crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        }
+
+        // nothing is visible before the commit
+        assert!(query_overlap(&deps, vec![first.clone(), second.clone()]).is_empty());
+
+        // only the admin that started the session can commit it
+        let msg = HandleMsg::AddAdmin {
+            address: HumanAddr::from("other"),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = HandleMsg::CommitImport { session_id };
+        assert!(crate::contract::handle(&mut deps, mock_env("other", &[]), msg.clone()).is_err());
+
+        crate::contract::handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(query_overlap(&deps, vec![first, second]).len(), 2);
+
+        // the session is closed after the commit
+        assert!(crate::contract::handle(&mut deps, env.clone(), msg).is_err());
+
+        // aborted sessions are never imported
+        let session_id = begin(&mut deps);
+        let third = location(525531150, 134378710, 1600693951455);
+        let msg = HandleMsg::ImportChunk {
+            session_id,
+            data: GoogleTakeoutHistory {
+                locations: vec![third.clone()],
+            },
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = HandleMsg::AbortImport { session_id };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = HandleMsg::CommitImport { session_id };
+        assert!(crate::contract::handle(&mut deps, env.clone(), msg).is_err());
+        assert!(query_overlap(&deps, vec![third.clone()]).is_empty());
+
+        // sessions are limited in size
+        let session_id = begin(&mut deps);
+        let msg = HandleMsg::ImportChunk {
+            session_id,
+            data: GoogleTakeoutHistory {
+                locations: vec![third.clone(); MAX_SESSION_POINTS as usize + 1],
+            },
+        };
+        assert!(crate::contract::handle(&mut deps, env.clone(), msg).is_err());
+        for chunk in 0..=MAX_SESSION_CHUNKS {
+            let msg = HandleMsg::ImportChunk {
+                session_id,
+                data: GoogleTakeoutHistory {
+                    locations: vec![third.clone()],
+                },
+            };
+            let res = crate::contract::handle(&mut deps, env.clone(), msg);
+            assert_eq!(res.is_ok(), chunk < MAX_SESSION_CHUNKS);
+        }
+
+        // sessions which are not committed in time expire, and are removed by ProcessExpiry
+        let mut later = env.clone();
+        later.block.time += SESSION_TTL / 1000 + 1;
+        let msg = HandleMsg::CommitImport { session_id };
+        assert!(crate::contract::handle(&mut deps, later.clone(), msg).is_err());
+        assert!(ImportSession::load(&deps.storage, session_id).is_ok());
+
+        let msg = HandleMsg::ProcessExpiry { limit: None };
+        let res = crate::contract::handle(&mut deps, later, msg).unwrap();
+        assert!(res.log.contains(&log("expired_sessions", 1)));
+        assert!(ImportSession::load(&deps.storage, session_id).is_err());
+        assert!(query_overlap(&deps, vec![third]).is_empty());
+    }
+
+    #[test]
+    pub fn test_delete_contributor() {
+        let (_, mut deps, env) = init_helper();
+
+        let theirs = location(525331150, 134378710, 1600693951455);
+        let others = location(525331150, 134378710, 1600694951455);
+        for (dp, contributor) in vec![
+            (theirs.clone(), Some("contributor".to_string())),
+            (others.clone(), None),
+        ] {
+            let msg = ImportGoogleLocations {
+                data: GoogleTakeoutHistory {
+                    locations: vec![dp],
+                },
+                contributor,
+                stay_points: None,
+            };
+            crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        }
+
+        let msg = HandleMsg::DeleteContributor {
+            contributor: "contributor".to_string(),
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(res.log[0].value, "1");
+
+        assert!(query_overlap(&deps, vec![theirs]).is_empty());
+        assert_eq!(query_overlap(&deps, vec![others]).len(), 1);
+
+        let hotspots = HotspotMap::load(&deps.storage).unwrap();
+        assert_eq!(hotspots.locations.values().sum::<u32>(), 1);
+
+        // the contributor's record is gone, so deleting again does nothing
+        let res = crate::contract::handle(&mut deps, env, msg).unwrap();
+        assert_eq!(res.log[0].value, "0");
+    }
+
+    #[test]
+    pub fn test_signed_coordinates() {
+        let (_, mut deps, env) = init_helper();
+
+        // Buenos Aires
+        let south_west = location(-346037220, -583815920, 1600693951455);
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![south_west.clone()],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let matches = query_overlap(&deps, vec![south_west.clone()]);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].geohash, south_west.hash().unwrap());
+
+        for (lat, lng) in vec![
+            (900000001, 0),
+            (-900000001, 0),
+            (0, 1800000001),
+            (0, -1800000001),
+        ] {
+            let msg = ImportGoogleLocations {
+                data: GoogleTakeoutHistory {
+                    locations: vec![location(lat, lng, 1600693951455)],
+                },
+                contributor: None,
+                stay_points: None,
+            };
+            let res = crate::contract::handle(&mut deps, env.clone(), msg);
+            match res {
+                Err(StdError::GenericErr { msg, .. }) => assert!(msg.contains("out of range")),
+                _ => panic!("expected out of range coordinates to be rejected"),
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_import_semantic_history() {
+        let (_, mut deps, env) = init_helper();
+
+        let msg: HandleMsg = serde_json::from_str(
+            r#"{"import_semantic_locations": {"data": {"timelineObjects": [
+                {"placeVisit": {
+                    "location": {"latitudeE7": 525331150, "longitudeE7": 134378710},
+                    "duration": {"startTimestampMs": "1600690000000", "endTimestampMs": "1600693600000"}
+                }},
+                {"activitySegment": {
+                    "startLocation": {"latitudeE7": 525331150, "longitudeE7": 134378710},
+                    "endLocation": {"latitudeE7": 525931150, "longitudeE7": 134378710},
+                    "duration": {"startTimestampMs": "1600693600000", "endTimestampMs": "1600694800000"},
+                    "waypointPath": {"waypoints": [{"latE7": 525631150, "lngE7": 134378710}]},
+                    "simplifiedRawPath": {"points": [
+                        {"latE7": 525431150, "lngE7": 134378710, "timestampMs": "1600693900000"}
+                    ]}
+                }}
+            ]}}}"#,
+        )
+        .unwrap();
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+
+        // anywhere during the visit
+        let during_visit = location(525331150, 134378710, 1600691800000);
+        // the waypoint is half way through the segment
+        let waypoint = location(525631150, 134378710, 1600694200000);
+        let raw_point = location(525431150, 134378710, 1600693900000);
+        let end = location(525931150, 134378710, 1600694800000);
+        let matches = query_overlap(&deps, vec![during_visit, waypoint, raw_point, end]);
+        assert_eq!(matches.len(), 4);
+
+        // but not before the visit started
+        let before_visit = location(525331150, 134378710, 1600689000000);
+        assert!(query_overlap(&deps, vec![before_visit]).is_empty());
+    }
+
+    #[test]
+    pub fn test_rfc3339_timestamps() {
+        let (_, mut deps, env) = init_helper();
+
+        // the newer export format, with an RFC 3339 timestamp and some extra fields
+        let msg: HandleMsg = serde_json::from_str(
+            r#"{"import_google_locations": {"data": {"locations": [{
+                "latitudeE7": 525331150,
+                "longitudeE7": 134378710,
+                "accuracy": 20,
+                "source": "WIFI",
+                "deviceTag": -1234567,
+                "timestamp": "2020-09-21T15:12:31.455+02:00"
+            }]}}}"#,
+        )
+        .unwrap();
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+
+        // matches both formats
+        let legacy = location(525331150, 134378710, 1600693951455);
+        let mut newer = legacy.clone();
+        newer.timestampMs = None;
+        newer.timestamp = Some("2020-09-21T13:12:31.455Z".to_string());
+        assert_eq!(query_overlap(&deps, vec![legacy, newer.clone()]).len(), 2);
+
+        newer.timestamp = Some("21/09/2020 13:12".to_string());
+        let query = QueryMsg::MatchDataPoints {
+            data_points: vec![newer],
+            gpx: None,
+            geojson: None,
+            window: None,
+            radius: None,
+        };
+        assert!(crate::contract::query(&deps, query).is_err());
+
+        // non-ASCII input is rejected, not sliced in the middle of a character
+        assert!(parse_rfc3339("2020-09-21T13:12:\u{20ac}Z").is_err());
+        assert!(parse_rfc3339("2020-09-21T13:12:31+0\u{e9}00").is_err());
+
+        // offsets have to be valid times as well
+        assert!(parse_rfc3339("2020-09-21T13:12:31+99:99").is_err());
+        assert!(parse_rfc3339("2020-09-21T13:12:31-24:00").is_err());
+        assert!(parse_rfc3339("2020-09-21T13:12:31+05:60").is_err());
+        assert_eq!(
+            parse_rfc3339("2020-09-21T13:12:31-23:59"),
+            Ok(1600693951000 + 23 * 3600000 + 59 * 60000)
+        );
+    }
+
+    #[test]
+    pub fn test_import_gpx() {
+        let (_, mut deps, env) = init_helper();
+
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+                <trk><trkseg>
+                    <trkpt lat="52.5331150" lon="13.4378710"><time>2020-09-21T13:12:31.455Z</time></trkpt>
+                    <trkpt lat="-34.6037220" lon="-58.3815920"><time>2020-09-21T13:20:00Z</time></trkpt>
+                </trkseg></trk>
+            </gpx>"#;
+        let msg = HandleMsg::ImportGpx {
+            data: gpx.to_string(),
+            contributor: None,
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.log[0].value, "2");
+
+        // query with the same GPX
+        let query = QueryMsg::MatchDataPoints {
+            data_points: vec![],
+            gpx: Some(gpx.to_string()),
+            geojson: None,
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, query).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::Overlap { data_points } => assert_eq!(data_points.len(), 2),
+            _ => panic!("unexpected query answer"),
+        }
+
+        // points without a timestamp are rejected
+        let gpx = r#"<gpx><trk><trkseg>
+                <trkpt lat="52.5331150" lon="13.4378710"><time>2020-09-21T13:12:31Z</time></trkpt>
+                <trkpt lat="52.5331150" lon="13.4378710"></trkpt>
+                <trkpt lat="52.5331150" lon="13.4378710"><ele>30</ele></trkpt>
+            </trkseg></trk></gpx>"#;
+        let msg = HandleMsg::ImportGpx {
+            data: gpx.to_string(),
+            contributor: None,
+        };
+        match crate::contract::handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert!(msg.starts_with("2 of 3")),
+            _ => panic!("expected points without a timestamp to be rejected"),
+        }
+    }
+
+    #[test]
+    pub fn test_import_geojson() {
+        let (_, mut deps, env) = init_helper();
+
+        let geojson = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature",
+             "geometry": {"type": "Point", "coordinates": [13.4378710, 52.5331150]},
+             "properties": {"time": "2020-09-21T13:12:31.455Z"}},
+            {"type": "Feature",
+             "geometry": {"type": "LineString", "coordinates": [[-58.381592, -34.603722, 25.0], [-58.382592, -34.604722]]},
+             "properties": {"coordTimes": ["2020-09-21T13:20:00Z", "2020-09-21T13:30:00Z"]}}
+        ]}"#;
+        let msg = HandleMsg::ImportGeoJson {
+            data: geojson.to_string(),
+            contributor: None,
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.log[0].value, "3");
+
+        let query = QueryMsg::MatchDataPoints {
+            data_points: vec![location(525331150, 134378710, 1600693951455)],
+            gpx: None,
+            geojson: Some(geojson.to_string()),
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, query).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::Overlap { data_points } => assert_eq!(data_points.len(), 4),
+            _ => panic!("unexpected query answer"),
+        }
+
+        // every vertex must have a time
+        let geojson = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature",
+             "geometry": {"type": "LineString", "coordinates": [[13.4378710, 52.5331150], [13.4378710, 52.5341150]]},
+             "properties": {"coordTimes": ["2020-09-21T13:20:00Z"]}}
+        ]}"#;
+        let msg = HandleMsg::ImportGeoJson {
+            data: geojson.to_string(),
+            contributor: None,
+        };
+        assert!(crate::contract::handle(&mut deps, env, msg).is_err());
+    }
+
+    #[test]
+    pub fn test_import_compact() {
+        let (_, mut deps, env) = init_helper();
+
+        let dp = location(525331150, 134378710, 1600693951455);
+        let geohash = dp.hash().unwrap();
+        // the same geohash, in its integer form
+        let geohash_int = geohash.bytes().fold(0u64, |acc, c| {
+            let value = b"0123456789bcdefghjkmnpqrstuvwxyz"
+                .iter()
+                .position(|b| *b == c)
+                .unwrap();
+            acc << 5 | value as u64
+        });
+
+        let msg = HandleMsg::ImportCompact {
+            cells: vec![
+                CompactCell {
+                    geohash: Some(geohash.clone()),
+                    geohash_int: None,
+                    times: vec![1600693951455, 600000],
+                },
+                CompactCell {
+                    geohash: None,
+                    geohash_int: Some(geohash_int),
+                    times: vec![1600695751455],
+                },
+            ],
+            contributor: None,
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.log[0].value, "3");
+
+        let matches = query_overlap(
+            &deps,
+            vec![
+                dp,
+                location(525331150, 134378710, 1600694551455),
+                location(525331150, 134378710, 1600695751455),
+            ],
+        );
+        assert_eq!(matches.len(), 3);
+
+        // wrong precision, and invalid characters
+        for bad in vec![&geohash[..7], "u33dbfcya"] {
+            let msg = HandleMsg::ImportCompact {
+                cells: vec![CompactCell {
+                    geohash: Some(bad.to_string()),
+                    geohash_int: None,
+                    times: vec![1600693951455],
+                }],
+                contributor: None,
+            };
+            assert!(crate::contract::handle(&mut deps, env.clone(), msg).is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_import_binary() {
+        let (_, mut deps, env) = init_helper();
+
+        fn varint(mut value: u64, out: &mut Vec<u8>) {
+            while value >= 0x80 {
+                out.push((value as u8) | 0x80);
+                value >>= 7;
+            }
+            out.push(value as u8);
+        }
+
+        let dp = location(525331150, 134378710, 1600693951455);
+        let geohash_int = dp.hash().unwrap().bytes().fold(0u64, |acc, c| {
+            let value = b"0123456789bcdefghjkmnpqrstuvwxyz"
+                .iter()
+                .position(|b| *b == c)
+                .unwrap();
+            acc << 5 | value as u64
+        });
+
+        // one cell, with two delta-encoded times
+        let mut body = vec![];
+        for value in vec![1, geohash_int, 2, 1600693951455, 600000] {
+            varint(value, &mut body);
+        }
+        let deflated = miniz_oxide::deflate::compress_to_vec(&body, 6);
+        let mut gzipped = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+        gzipped.extend(&deflated);
+        gzipped.extend(&[0; 8]);
+
+        for (compression, payload) in vec![(0, body.clone()), (1, deflated), (2, gzipped)] {
+            let mut data = vec![1, compression];
+            data.extend(payload);
+            let msg = HandleMsg::ImportBinary {
+                data: Binary::from(data),
+                contributor: None,
+            };
+            let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+            assert_eq!(res.log[0].value, "2");
+        }
+
+        let later = location(525331150, 134378710, 1600694551455);
+        assert_eq!(query_overlap(&deps, vec![dp, later]).len(), 2);
+
+        // unknown version, and a truncated body
+        for data in vec![vec![2, 0], vec![1, 0, body[0], body[1]]] {
+            let msg = HandleMsg::ImportBinary {
+                data: Binary::from(data),
+                contributor: None,
+            };
+            assert!(crate::contract::handle(&mut deps, env.clone(), msg).is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_max_accuracy() {
+        let (_, mut deps, env) = init_helper();
+
+        let msg = HandleMsg::SetMaxAccuracy {
+            max_accuracy: Some(100),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let mut accurate = location(525331150, 134378710, 1600693951455);
+        accurate.accuracy = Some(20);
+        let mut cell_tower = location(525431150, 134378710, 1600693951455);
+        cell_tower.accuracy = Some(2000);
+        let unknown = location(525531150, 134378710, 1600693951455);
+
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![accurate.clone(), cell_tower.clone(), unknown.clone()],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.log[0].value, "2");
+        assert_eq!(res.log[1].value, "1");
+
+        assert_eq!(
+            query_overlap(&deps, vec![accurate.clone(), unknown.clone()]).len(),
+            2
+        );
+
+        // inaccurate query inputs are not matched either
+        let msg = HandleMsg::SetMaxAccuracy { max_accuracy: None };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![cell_tower.clone()],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(query_overlap(&deps, vec![cell_tower.clone()]).len(), 1);
+
+        let msg = HandleMsg::SetMaxAccuracy {
+            max_accuracy: Some(100),
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+        assert!(query_overlap(&deps, vec![cell_tower]).is_empty());
+    }
+
+    #[test]
+    pub fn test_stay_points() {
+        let (_, mut deps, env) = init_helper();
+
+        // 40 minutes at (almost) the same place, and then somewhere else
+        let start: u64 = 1600690000000;
+        let mut locations: Vec<GoogleLocation> = (0..9)
+            .map(|i| {
+                location(
+                    525331150 + i as i64 % 2 * 10,
+                    134378710,
+                    start + i * 5 * 60 * 1000,
+                )
+            })
+            .collect();
+        locations.push(location(525931150, 134378710, start + 45 * 60 * 1000));
+
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory { locations },
+            contributor: Some("contributor".to_string()),
+            stay_points: Some(StayPoints {
+                max_distance: 20,
+                min_duration: 10 * 60 * 1000,
+                max_gap: None,
+            }),
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        // a single stay, and a single data point
+        assert_eq!(res.log[0].value, "2");
+
+        let during = location(525331150, 134378710, start + 22 * 60 * 1000);
+        let msg = QueryMsg::MatchTimes {
+            data_points: vec![during.clone()],
+            gpx: None,
+            geojson: None,
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, msg).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::OverlapTimes { matches } => {
+                assert_eq!(matches.len(), 1);
+                assert!(matches[0].times.is_empty());
+                assert_eq!(
+                    matches[0].stays,
+                    vec![Stay {
+                        start,
+                        end: start + 40 * 60 * 1000
+                    }]
+                );
+            }
+            _ => panic!("unexpected query answer"),
+        }
+
+        let just_after = location(525331150, 134378710, start + 43 * 60 * 1000);
+        let before = location(525331150, 134378710, start - 6 * 60 * 1000);
+        assert_eq!(
+            query_overlap(&deps, vec![during.clone(), just_after, before]).len(),
+            2
+        );
+
+        // a later import which overlaps the stay is merged with it
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: (6..13)
+                    .map(|i| location(525331150, 134378710, start + i * 5 * 60 * 1000))
+                    .collect(),
+            },
+            contributor: Some("other".to_string()),
+            stay_points: Some(StayPoints {
+                max_distance: 20,
+                min_duration: 10 * 60 * 1000,
+                max_gap: None,
+            }),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let bucket = Pointers::load(&deps.storage)
+            .unwrap()
+            .find_bucket(start)
+            .unwrap();
+        let cell = DailyBucket::load_cell(&deps.storage, &bucket, &during.hash().unwrap())
+            .unwrap()
+            .unwrap();
+        let minute: u64 = 60 * 1000;
+        assert_eq!(
+            cell.stays.periods,
+            vec![
+                MergedStay {
+                    start,
+                    end: start + 30 * minute - 1,
+                    count: 1
+                },
+                MergedStay {
+                    start: start + 30 * minute,
+                    end: start + 40 * minute,
+                    count: 2
+                },
+                MergedStay {
+                    start: start + 40 * minute + 1,
+                    end: start + 60 * minute,
+                    count: 1
+                }
+            ]
+        );
+        assert_eq!(
+            cell.stays_in(start, start),
+            vec![Stay {
+                start,
+                end: start + 60 * minute
+            }]
+        );
+
+        // stays are removed with the rest of the contributor's data, so only the time of the
+        // other stay is left
+        let only_deleted = location(525331150, 134378710, start + 10 * minute);
+        let later = location(525331150, 134378710, start + 55 * minute);
+        let msg = HandleMsg::DeleteContributor {
+            contributor: "contributor".to_string(),
+        };
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.log[0].value, "2");
+        assert_eq!(query_overlap(&deps, vec![later.clone()]).len(), 1);
+        assert!(query_overlap(&deps, vec![only_deleted, during.clone()]).is_empty());
+        let cell = DailyBucket::load_cell(&deps.storage, &bucket, &during.hash().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            cell.stays_in(start, start + 60 * minute),
+            vec![Stay {
+                start: start + 30 * minute,
+                end: start + 60 * minute
+            }]
+        );
+
+        let msg = HandleMsg::DeleteContributor {
+            contributor: "other".to_string(),
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+        assert!(query_overlap(&deps, vec![during, later]).is_empty());
+    }
+
+    fn interval(latitude_e7: i64, start: u64, end: u64) -> LocationInterval {
+        LocationInterval {
+            latitudeE7: latitude_e7,
+            longitudeE7: 134378710,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    pub fn test_match_intervals() {
+        let (_, mut deps, env) = init_helper();
+
+        // two overlapping visits at the same place are stored as a single interval
+        let msg: HandleMsg = serde_json::from_str(
+            r#"{"import_semantic_locations": {"data": {"timelineObjects": [
+                {"placeVisit": {
+                    "location": {"latitudeE7": 525331150, "longitudeE7": 134378710},
+                    "duration": {"startTimestampMs": "1600690000000", "endTimestampMs": "1600693600000"}
+                }},
+                {"placeVisit": {
+                    "location": {"latitudeE7": 525331150, "longitudeE7": 134378710},
+                    "duration": {"startTimestampMs": "1600691800000", "endTimestampMs": "1600697200000"}
+                }}
+            ]}}}"#,
+        )
+        .unwrap();
+        let res = crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.log[0].value, "1");
+
+        let msg = QueryMsg::MatchTimes {
+            data_points: vec![location(525331150, 134378710, 1600695000000)],
+            gpx: None,
+            geojson: None,
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, msg).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::OverlapTimes { matches } => {
+                assert_eq!(
+                    matches[0].stays,
+                    vec![Stay {
+                        start: 1600690000000,
+                        end: 1600697200000
+                    }]
+                );
+            }
+            _ => panic!("unexpected query answer"),
+        }
+
+        // and a single data point somewhere else
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![location(525931150, 134378710, 1600690000000)],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+
+        let intersects_stay = interval(525331150, 1600696000000, 1600700000000);
+        let after_stay = interval(525331150, 1600698000000, 1600700000000);
+        let contains_point = interval(525931150, 1600686000000, 1600694000000);
+        let msg = QueryMsg::MatchIntervals {
+            intervals: vec![intersects_stay, after_stay, contains_point],
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, msg).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::IntervalOverlap { intervals } => {
+                assert_eq!(intervals.len(), 2);
+                assert_eq!(intervals[0].start, 1600696000000);
+                assert_eq!(intervals[1].start, 1600686000000);
+            }
+            _ => panic!("unexpected query answer"),
+        }
+
+        let msg = QueryMsg::MatchIntervals {
+            intervals: vec![interval(525331150, 1600700000000, 1600696000000)],
+            window: None,
+            radius: None,
+        };
+        assert!(crate::contract::query(&deps, msg).is_err());
+    }
+
+    #[test]
+    pub fn test_match_window() {
+        let (_, mut deps, env) = init_helper();
+
+        let time: u64 = 1600690000000;
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![location(525331150, 134378710, time)],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let minutes_before = |m: u64| location(525331150, 134378710, time - m * 60 * 1000);
+        let minutes_after = |m: u64| location(525331150, 134378710, time + m * 60 * 1000);
+
+        // 5 minutes on both sides by default
+        let matches = query_overlap(
+            &deps,
+            vec![minutes_before(3), minutes_after(3), minutes_after(6)],
+        );
+        assert_eq!(matches.len(), 2);
+
+        // a longer window after the data was stored, for this query only
+        let msg = QueryMsg::MatchDataPoints {
+            data_points: vec![minutes_before(3), minutes_after(3), minutes_after(20)],
+            gpx: None,
+            geojson: None,
+            window: Some(MatchWindow {
+                before: 0,
+                after: 30 * 60 * 1000,
+            }),
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, msg).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::Overlap { data_points } => {
+                assert_eq!(data_points.len(), 2);
+                assert_eq!(data_points[1].timestamp_ms, time + 20 * 60 * 1000);
+            }
+            _ => panic!("unexpected query answer"),
+        }
+
+        // or for the whole contract
+        let msg = HandleMsg::SetMatchWindow {
+            match_window: Some(MatchWindow {
+                before: 0,
+                after: 30 * 60 * 1000,
+            }),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(
+            query_overlap(&deps, vec![minutes_before(3), minutes_after(20)]).len(),
+            1
+        );
+
+        let msg = HandleMsg::SetMatchWindow {
+            match_window: Some(MatchWindow {
+                before: 2 * ONE_DAY,
+                after: 0,
+            }),
+        };
+        assert!(crate::contract::handle(&mut deps, env, msg).is_err());
+    }
+
+    fn match_in_radius(
+        deps: &Extern<MockStorage, MockApi, MockQuerier>,
+        data_point: GoogleLocation,
+        radius: Option<u32>,
+    ) -> StdResult<Vec<GeoLocationTime>> {
+        let msg = QueryMsg::MatchDataPoints {
+            data_points: vec![data_point],
+            gpx: None,
+            geojson: None,
+            window: None,
+            radius,
+        };
+        match from_binary(&crate::contract::query(deps, msg)?)? {
+            QueryAnswer::Overlap { data_points } => Ok(data_points),
+            _ => panic!("unexpected query answer"),
+        }
+    }
+
+    #[test]
+    pub fn test_match_radius() {
+        let (_, mut deps, env) = init_helper();
+
+        let time: u64 = 1600690000000;
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![location(525331150, 134378710, time)],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        // about 50 meters north
+        let nearby = location(525335650, 134378710, time);
+        assert!(match_in_radius(&deps, nearby.clone(), None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            match_in_radius(&deps, nearby.clone(), Some(60))
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // about 1km north
+        let far = location(525421150, 134378710, time);
+        assert!(match_in_radius(&deps, far.clone(), Some(100))
+            .unwrap()
+            .is_empty());
+        assert!(match_in_radius(&deps, far.clone(), Some(2000)).is_err());
+
+        let msg = HandleMsg::SetMatchLimits {
+            match_limits: Some(MatchLimits {
+                max_radius: 50000,
+                max_window: ONE_HOUR,
+            }),
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+        assert_eq!(match_in_radius(&deps, far, Some(2000)).unwrap().len(), 1);
+
+        // about 30km north, which searches cells larger than an index shard
+        let very_far = location(528031150, 134378710, time);
+        assert!(match_in_radius(&deps, very_far.clone(), Some(2000))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            match_in_radius(&deps, very_far, Some(40000)).unwrap().len(),
+            1
+        );
+
+        // the time window is limited as well
+        let msg = QueryMsg::MatchDataPoints {
+            data_points: vec![nearby],
+            gpx: None,
+            geojson: None,
+            window: Some(MatchWindow {
+                before: 2 * ONE_HOUR,
+                after: 0,
+            }),
+            radius: None,
+        };
+        assert!(crate::contract::query(&deps, msg).is_err());
+    }
+
+    #[test]
+    pub fn test_match_distance() {
+        let (_, mut deps, env) = init_helper();
+
+        // intervals are searched the same way as data points
+        let match_intervals = |deps: &Extern<MockStorage, MockApi, MockQuerier>,
+                               dp: &GoogleLocation,
+                               radius: Option<u32>| {
+            let time = dp.timestamp_ms().unwrap();
+            let msg = QueryMsg::MatchIntervals {
+                intervals: vec![LocationInterval {
+                    latitudeE7: dp.latitudeE7,
+                    longitudeE7: dp.longitudeE7,
+                    start: time,
+                    end: time + 60 * 1000,
+                }],
+                window: None,
+                radius,
+            };
+            match from_binary(&crate::contract::query(deps, msg).unwrap()).unwrap() {
+                QueryAnswer::IntervalOverlap { intervals } => intervals.len(),
+                _ => panic!("unexpected query answer"),
+            }
+        };
+
+        let time: u64 = 1600690000000;
+        let import = |latitude_e7: i64, contributor: &str| ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![location(latitude_e7, 134378710, time)],
+            },
+            contributor: Some(contributor.to_string()),
+            stay_points: None,
+        };
+
+        // imported before positions are stored, so it is matched by geohash only
+        let msg = import(525431150, "before");
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let msg = HandleMsg::SetMatchDistance {
+            match_distance: Some(10),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let msg = import(525331150, "after");
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        // about 7 and 15 meters north of each point
+        let seven_meters = location(525331750, 134378710, time);
+        let fifteen_meters = location(525332500, 134378710, time);
+        let matches = query_overlap(&deps, vec![seven_meters.clone(), fifteen_meters.clone()]);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].timestamp_ms, time);
+        assert_eq!(matches[0].geohash, seven_meters.hash().unwrap());
+
+        let old_fifteen_meters = location(525432500, 134378710, time);
+        assert_eq!(query_overlap(&deps, vec![old_fifteen_meters]).len(), 1);
+
+        // data imported in a session keeps its position. Both points are in the same cell, about
+        // 4 meters apart
+        let msg = HandleMsg::SetMatchDistance {
+            match_distance: Some(2),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let res = crate::contract::handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::BeginImport { contributor: None },
+        );
+        let session_id = match from_binary(&res.unwrap().data.unwrap()).unwrap() {
+            HandleAnswer::BeginImport { session_id } => session_id,
+        };
+        let msg = HandleMsg::ImportChunk {
+            session_id,
+            data: GoogleTakeoutHistory {
+                locations: vec![location(525331050, 134378710, time + ONE_HOUR)],
+            },
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = HandleMsg::CommitImport { session_id };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let near = location(525331050, 134378710, time + ONE_HOUR);
+        let far = location(525331420, 134378710, time + ONE_HOUR);
+        assert_eq!(near.hash().unwrap(), far.hash().unwrap());
+        assert_eq!(query_overlap(&deps, vec![near.clone()]).len(), 1);
+        assert!(query_overlap(&deps, vec![far.clone()]).is_empty());
+        assert_eq!(match_intervals(&deps, &near, None), 1);
+        assert_eq!(match_intervals(&deps, &far, None), 0);
+
+        let msg = HandleMsg::SetMatchDistance {
+            match_distance: Some(10),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        // a radius replaces the contract's distance
+        assert_eq!(
+            match_in_radius(&deps, fifteen_meters.clone(), Some(20))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(match_intervals(&deps, &fifteen_meters, None), 0);
+        assert_eq!(match_intervals(&deps, &fifteen_meters, Some(20)), 1);
+
+        // positions are removed with the rest of the contributor's data
+        let msg = HandleMsg::DeleteContributor {
+            contributor: "after".to_string(),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert!(query_overlap(&deps, vec![seven_meters]).is_empty());
+
+        // about 9.5 meters east, 4 cells away at this latitude
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![location(525331150, 134380459, time + 2 * ONE_HOUR)],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+        let west = location(525331150, 134379057, time + 2 * ONE_HOUR);
+        assert_eq!(query_overlap(&deps, vec![west.clone()]).len(), 1);
+        assert_eq!(match_intervals(&deps, &west, None), 1);
+    }
+
+    #[test]
+    pub fn test_neighborhood() {
+        let center = "u33dbfcyr".to_string();
+
+        let first_ring = neighborhood(&center, 1).unwrap();
+        assert_eq!(first_ring.len(), 8);
+        let two_rings = neighborhood(&center, 2).unwrap();
+        assert_eq!(two_rings.len(), 24);
+        assert_eq!(two_rings[..8], first_ring[..]);
+        assert!(!two_rings.contains(&center));
+
+        let mut unique = two_rings.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), two_rings.len());
+
+        assert!(neighborhood(&center, MAX_RINGS + 1).is_err());
+
+        // small radii are searched with full precision cells, larger ones with coarser cells
+        assert_eq!(SearchArea::for_radius(0, 0.0), SearchArea::default());
+        assert_eq!(
+            SearchArea::for_radius(10, 0.0),
+            SearchArea {
+                precision: 9,
+                rings: 3
+            }
+        );
+        assert_eq!(SearchArea::for_radius(100, 0.0).precision, 7);
+
+        // cells are narrower away from the equator, so more of them are needed
+        assert_eq!(
+            SearchArea::for_radius(10, 52.5),
+            SearchArea {
+                precision: 9,
+                rings: 4
+            }
+        );
+        assert_eq!(
+            SearchArea::for_radius(10, -52.5),
+            SearchArea::for_radius(10, 52.5)
+        );
+    }
+
+    #[test]
+    pub fn test_exposure_summary() {
+        let (_, mut deps, env) = init_helper();
+
+        let time: u64 = 1600690000000;
+        let minute: u64 = 60 * 1000;
+        let mut locations: Vec<GoogleLocation> = (0..5)
+            .map(|i| location(525331150, 134378710, time + i * 5 * minute))
+            .collect();
+        locations.push(location(525331150, 134378710, time + 60 * minute));
+        locations.push(location(525331150, 134378710, time + ONE_DAY));
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory { locations },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+
+        // 20 minutes at the same place, a short trip somewhere else, and back for a moment. Then
+        // again on the next day
+        let mut trajectory: Vec<GoogleLocation> = (0..5)
+            .map(|i| location(525331150, 134378710, time + i * 5 * minute))
+            .collect();
+        trajectory.push(location(525931150, 134378710, time + 25 * minute));
+        trajectory.push(location(525331150, 134378710, time + 60 * minute));
+        trajectory.push(location(525331150, 134378710, time + ONE_DAY));
+
+        let msg = QueryMsg::ExposureSummary {
+            data_points: trajectory,
+            gpx: None,
+            geojson: None,
+            window: None,
+            radius: None,
+        };
+        let res = crate::contract::query(&deps, msg).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::ExposureSummary { days } => {
+                assert_eq!(
+                    days,
+                    vec![
+                        DailyExposure {
+                            day: 1600646400000,
+                            minutes: 21,
+                            episodes: 2,
+                            longest_episode: 20,
+                        },
+                        DailyExposure {
+                            day: 1600646400000 + ONE_DAY,
+                            minutes: 1,
+                            episodes: 1,
+                            longest_episode: 1,
+                        }
+                    ]
+                );
+            }
+            _ => panic!("unexpected query answer"),
+        }
+    }
+
+    #[test]
+    pub fn test_risk_score() {
+        let (_, mut deps, env) = init_helper();
+
+        let time: u64 = 1600690000000;
+        let minute: u64 = 60 * 1000;
+        let query = |trajectory: Vec<GoogleLocation>| QueryMsg::RiskScore {
+            data_points: trajectory,
+            gpx: None,
+            geojson: None,
+            window: None,
+            time: Some(time + ONE_DAY + ONE_HOUR),
+        };
+
+        let res = crate::contract::query(&deps, query(vec![]));
+        assert!(res.is_err());
+
+        // a suspected case stayed for 20 minutes, and a confirmed case visited the next day
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: (0..5)
+                    .map(|i| location(525331150, 134378710, time + i * 5 * minute))
+                    .collect(),
+            },
+            contributor: Some("suspect".to_string()),
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = HandleMsg::SetContributorStatus {
+            contributor: "suspect".to_string(),
+            status: InfectionStatus::Suspected,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = ImportGoogleLocations {
+            data: GoogleTakeoutHistory {
+                locations: vec![location(525331150, 134378710, time + ONE_DAY)],
+            },
+            contributor: None,
+            stay_points: None,
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+
+        let risk_config = RiskConfig {
+            proximity: vec![
+                ProximityTier {
+                    radius: 10,
+                    weight: 3,
+                },
+                ProximityTier {
+                    radius: 100,
+                    weight: 1,
+                },
+            ],
+            duration: vec![
+                DurationWeight {
+                    min_minutes: 0,
+                    weight: 1,
+                },
+                DurationWeight {
+                    min_minutes: 15,
+                    weight: 2,
+                },
+            ],
+            days_since_exposure: vec![2, 1],
+            confirmed: 2,
+            suspected: 1,
+        };
+        let mut invalid = risk_config.clone();
+        invalid.proximity.reverse();
+        let msg = HandleMsg::SetRiskConfig {
+            risk_config: Some(invalid),
+        };
+        assert!(crate::contract::handle(&mut deps, env.clone(), msg).is_err());
+        let msg = HandleMsg::SetRiskConfig {
+            risk_config: Some(risk_config),
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+
+        // the same 20 minutes at the same place, and about 50 meters away on the next day
+        let mut trajectory: Vec<GoogleLocation> = (0..5)
+            .map(|i| location(525331150, 134378710, time + i * 5 * minute))
+            .collect();
+        trajectory.push(location(525335650, 134378710, time + ONE_DAY));
+
+        let res = crate::contract::query(&deps, query(trajectory)).unwrap();
+        match from_binary(&res).unwrap() {
+            QueryAnswer::RiskScore { score, exposures } => {
+                assert_eq!(
+                    exposures,
+                    vec![
+                        ExposureRisk {
+                            start: time,
+                            end: time + 20 * minute,
+                            minutes: 20,
+                            days_since_exposure: 1,
+                            status: InfectionStatus::Suspected,
+                            proximity_weight: 3,
+                            duration_weight: 2,
+                            days_weight: 1,
+                            status_weight: 1,
+                            score: 6,
+                        },
+                        ExposureRisk {
+                            start: time + ONE_DAY,
+                            end: time + ONE_DAY,
+                            minutes: 1,
+                            days_since_exposure: 0,
+                            status: InfectionStatus::Confirmed,
+                            proximity_weight: 1,
+                            duration_weight: 1,
+                            days_weight: 2,
+                            status_weight: 2,
+                            score: 4,
+                        }
+                    ]
+                );
+                assert_eq!(score, 10);
+            }
+            _ => panic!("unexpected query answer"),
+        }
+    }
+
+    #[test]
+    pub fn test_infection_status() {
+        let time: u64 = 1600690000000;
+        let mut cell = Cell::default();
+        cell.times.insert(time);
+
+        // marking a data point is idempotent, so a single change back undoes it
+        assert!(cell.set_time_status(time, InfectionStatus::Suspected));
+        assert!(!cell.set_time_status(time, InfectionStatus::Suspected));
+        assert_eq!(
+            cell.matched_status(time, time, None),
+            Some(InfectionStatus::Suspected)
+        );
+
+        assert!(cell.set_time_status(time, InfectionStatus::Confirmed));
+        assert!(!cell.set_time_status(time, InfectionStatus::Confirmed));
+        assert_eq!(
+            cell.matched_status(time, time, None),
+            Some(InfectionStatus::Confirmed)
+        );
+        assert_eq!(cell.matched_status(time + 1, time + 1, None), None);
+
+        // several contributors at the same place and time each have their own status
+        let (_, mut deps, env) = init_helper();
+        for contributor in vec!["a", "b", "c"] {
+            let msg = ImportGoogleLocations {
+                data: GoogleTakeoutHistory {
+                    locations: vec![location(525331150, 134378710, time)],
+                },
+                contributor: Some(contributor.to_string()),
+                stay_points: None,
+            };
+            crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        }
+        for contributor in vec!["b", "c"] {
+            let msg = HandleMsg::SetContributorStatus {
+                contributor: contributor.to_string(),
+                status: InfectionStatus::Suspected,
+            };
+            crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        }
+
+        let ghash: GeoLocationTime = location(525331150, 134378710, time).try_into().unwrap();
+        let id = Pointers::load(&deps.storage)
+            .unwrap()
+            .find_bucket(time)
+            .unwrap();
+        let status = |deps: &Extern<MockStorage, MockApi, MockQuerier>| {
+            DailyBucket::load_cell(&deps.storage, &id, &ghash.geohash)
+                .unwrap()
+                .unwrap()
+                .matched_status(time, time, None)
+        };
+        assert_eq!(status(&deps), Some(InfectionStatus::Confirmed));
+
+        let msg = HandleMsg::DeleteContributor {
+            contributor: "a".to_string(),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(status(&deps), Some(InfectionStatus::Suspected));
+
+        // deleting one suspected case keeps the others suspected
+        let msg = HandleMsg::DeleteContributor {
+            contributor: "b".to_string(),
+        };
+        crate::contract::handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(status(&deps), Some(InfectionStatus::Suspected));
+
+        let msg = HandleMsg::SetContributorStatus {
+            contributor: "c".to_string(),
+            status: InfectionStatus::Confirmed,
+        };
+        crate::contract::handle(&mut deps, env, msg).unwrap();
+        assert_eq!(status(&deps), Some(InfectionStatus::Confirmed));
+    }
 }
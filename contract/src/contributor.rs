@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use bincode2;
+use cosmwasm_std::{
+    log, Api, Env, Extern, HandleResponse, Querier, ReadonlyStorage, StdError, StdResult, Storage,
+};
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+use serde::{Deserialize, Serialize};
+
+use crate::bucket::{BucketId, DailyBucket, Stay};
+use crate::expiry::ExpiryQueue;
+use crate::geohash::Position;
+use crate::hotspotmap::{HotSpots, HotspotMap};
+use crate::msg::InfectionStatus;
+use crate::pointer::Pointers;
+
+pub static CONTRIBUTORS_KEY: &[u8] = b"contributors";
+pub static CONTRIBUTOR_BUCKETS_KEY: &[u8] = b"contributor_buckets";
+pub static BUCKET_CONTRIBUTORS_KEY: &[u8] = b"bucket_contributors";
+pub static CONTRIBUTOR_STATUS_KEY: &[u8] = b"contributor_status";
+
+/// `Contribution` records every data point that was imported for a single contributor into a
+/// single bucket, by geohash cell. The data in the buckets themselves is anonymous, so this is the
+/// only way to find (and remove) the data of a specific contributor. Contributions are removed
+/// with the bucket when it expires, so they are kept no longer than the data itself.
+///
+/// Contributors are identified by an opaque string, which is chosen by whoever imports the data.
+/// It should not be something that identifies the person directly, like a name - a hash of an
+/// internal identifier works well.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Contribution(pub HashMap<String, ContributedCell>);
+
+/// The entries a contributor added to a single cell. Unlike the cell itself, stays are kept
+/// exactly as they were imported, so they can be found in the merged stays of the cell
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ContributedCell {
+    pub times: Vec<u64>,
+    pub stays: Vec<Stay>,
+    pub positions: Vec<(u64, Position)>,
+}
+
+impl Contribution {
+    pub fn store<S: Storage>(
+        &self,
+        store: &mut S,
+        contributor: &str,
+        id: &BucketId,
+    ) -> StdResult<()> {
+        let mut config_store =
+            PrefixedStorage::multilevel(&[CONTRIBUTORS_KEY, contributor.as_bytes()], store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error serializing contribution"))?;
+
+        config_store.set(&id.to_bytes(), &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(
+        store: &S,
+        contributor: &str,
+        id: &BucketId,
+    ) -> StdResult<Self> {
+        let config_store =
+            ReadonlyPrefixedStorage::multilevel(&[CONTRIBUTORS_KEY, contributor.as_bytes()], store);
+        if let Some(temp) = config_store.get(&id.to_bytes()) {
+            let contribution: Self = bincode2::deserialize(&temp)
+                .map_err(|_| StdError::generic_err("Error deserializing contribution"))?;
+            return Ok(contribution);
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn remove<S: Storage>(store: &mut S, contributor: &str, id: &BucketId) {
+        let mut config_store =
+            PrefixedStorage::multilevel(&[CONTRIBUTORS_KEY, contributor.as_bytes()], store);
+        config_store.remove(&id.to_bytes());
+    }
+
+    pub fn insert(&mut self, ghash: &str, time: u64, position: Option<Position>) {
+        let cell = self.0.entry(ghash.to_string()).or_default();
+        cell.times.push(time);
+        if let Some(position) = position {
+            cell.positions.push((time, position));
+        }
+    }
+
+    pub fn insert_stay(&mut self, ghash: &str, stay: Stay) {
+        self.0
+            .entry(ghash.to_string())
+            .or_default()
+            .stays
+            .push(stay);
+    }
+}
+
+/// The buckets a contributor has a `Contribution` in. Buckets which were already cleared are
+/// pruned the next time data is imported for the contributor
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ContributorBuckets(pub Vec<BucketId>);
+
+impl ContributorBuckets {
+    pub fn store<S: Storage>(&self, store: &mut S, contributor: &str) -> StdResult<()> {
+        let mut config_store = PrefixedStorage::new(CONTRIBUTOR_BUCKETS_KEY, store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error serializing contributor buckets"))?;
+
+        config_store.set(contributor.as_bytes(), &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(store: &S, contributor: &str) -> StdResult<Self> {
+        let config_store = ReadonlyPrefixedStorage::new(CONTRIBUTOR_BUCKETS_KEY, store);
+        if let Some(temp) = config_store.get(contributor.as_bytes()) {
+            let buckets: Self = bincode2::deserialize(&temp)
+                .map_err(|_| StdError::generic_err("Error deserializing contributor buckets"))?;
+            return Ok(buckets);
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn remove<S: Storage>(store: &mut S, contributor: &str) {
+        let mut config_store = PrefixedStorage::new(CONTRIBUTOR_BUCKETS_KEY, store);
+        config_store.remove(contributor.as_bytes());
+    }
+}
+
+/// The contributors that have a `Contribution` in a bucket, so their contributions can be removed
+/// when the bucket expires
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BucketContributors(pub Vec<String>);
+
+impl BucketContributors {
+    pub fn store<S: Storage>(&self, store: &mut S, id: &BucketId) -> StdResult<()> {
+        let mut config_store = PrefixedStorage::new(BUCKET_CONTRIBUTORS_KEY, store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error serializing bucket contributors"))?;
+
+        config_store.set(&id.to_bytes(), &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(store: &S, id: &BucketId) -> StdResult<Self> {
+        let config_store = ReadonlyPrefixedStorage::new(BUCKET_CONTRIBUTORS_KEY, store);
+        if let Some(temp) = config_store.get(&id.to_bytes()) {
+            let contributors: Self = bincode2::deserialize(&temp)
+                .map_err(|_| StdError::generic_err("Error deserializing bucket contributors"))?;
+            return Ok(contributors);
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn remove<S: Storage>(store: &mut S, id: &BucketId) {
+        let mut config_store = PrefixedStorage::new(BUCKET_CONTRIBUTORS_KEY, store);
+        config_store.remove(&id.to_bytes());
+    }
+}
+
+/// The contributions of a single contributor that an import adds to. Only the buckets the import
+/// touches are loaded and rewritten
+pub struct Contributions {
+    contributor: String,
+    buckets: HashMap<BucketId, Contribution>,
+}
+
+impl Contributions {
+    pub fn new(contributor: &str) -> Self {
+        Self {
+            contributor: contributor.to_string(),
+            buckets: HashMap::default(),
+        }
+    }
+
+    /// The contribution to a bucket, loaded from storage the first time we need it
+    pub fn bucket<S: ReadonlyStorage>(
+        &mut self,
+        store: &S,
+        id: BucketId,
+    ) -> StdResult<&mut Contribution> {
+        if !self.buckets.contains_key(&id) {
+            let contribution = Contribution::load(store, &self.contributor, &id)?;
+            self.buckets.insert(id, contribution);
+        }
+
+        Ok(self.buckets.get_mut(&id).unwrap())
+    }
+
+    /// Store the contributions, and add new buckets to the lists of the contributor and the
+    /// buckets
+    pub fn store<S: Storage>(&self, store: &mut S) -> StdResult<()> {
+        let mut buckets = ContributorBuckets::load(store, &self.contributor)?;
+
+        // buckets which were cleared no longer have a contribution
+        let pointers = Pointers::load(store)?;
+        let queue = ExpiryQueue::load(store)?;
+        buckets
+            .0
+            .retain(|id| pointers.0.iter().any(|p| &p.bucket == id) || queue.buckets.contains(id));
+
+        for (id, contribution) in &self.buckets {
+            contribution.store(store, &self.contributor, id)?;
+
+            if !buckets.0.contains(id) {
+                buckets.0.push(*id);
+
+                let mut contributors = BucketContributors::load(store, id)?;
+                contributors.0.push(self.contributor.clone());
+                contributors.store(store, id)?;
+            }
+        }
+
+        buckets.store(store, &self.contributor)
+    }
+}
+
+/// Remove the contributions to a bucket that is being cleared
+pub fn expire_contributions<S: Storage>(store: &mut S, id: &BucketId) -> StdResult<()> {
+    for contributor in BucketContributors::load(store, id)?.0 {
+        Contribution::remove(store, &contributor, id);
+    }
+    BucketContributors::remove(store, id);
+
+    Ok(())
+}
+
+/// The infection status of a contributor. Only suspected cases are stored, everyone else is a
+/// confirmed case
+pub fn contributor_status<S: ReadonlyStorage>(
+    store: &S,
+    contributor: &str,
+) -> StdResult<InfectionStatus> {
+    let status_store = ReadonlyPrefixedStorage::new(CONTRIBUTOR_STATUS_KEY, store);
+    if let Some(temp) = status_store.get(contributor.as_bytes()) {
+        let status: InfectionStatus = bincode2::deserialize(&temp)
+            .map_err(|_| StdError::generic_err("Error deserializing contributor status"))?;
+        return Ok(status);
+    }
+
+    Ok(InfectionStatus::default())
+}
+
+fn store_contributor_status<S: Storage>(
+    store: &mut S,
+    contributor: &str,
+    status: InfectionStatus,
+) -> StdResult<()> {
+    let mut status_store = PrefixedStorage::new(CONTRIBUTOR_STATUS_KEY, store);
+    if status == InfectionStatus::default() {
+        status_store.remove(contributor.as_bytes());
+        return Ok(());
+    }
+
+    let as_bytes = bincode2::serialize(&status)
+        .map_err(|_| StdError::generic_err("Error serializing contributor status"))?;
+    status_store.set(contributor.as_bytes(), &as_bytes);
+
+    Ok(())
+}
+
+/// Change the infection status of a contributor, and of all the data which was already imported
+/// for them (e.g. when a suspected case gets a positive test)
+pub fn set_contributor_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    contributor: String,
+    status: InfectionStatus,
+) -> StdResult<HandleResponse> {
+    if contributor_status(&deps.storage, &contributor)? == status {
+        return Ok(HandleResponse {
+            messages: vec![],
+            log: vec![log("updated", 0)],
+            data: None,
+        });
+    }
+
+    let mut updated: u64 = 0;
+    for id in ContributorBuckets::load(&deps.storage, &contributor)?.0 {
+        let cells = Contribution::load(&deps.storage, &contributor, &id)?.0;
+        let mut bucket = DailyBucket::new(id);
+        for (ghash, cell) in cells {
+            for time in cell.times {
+                bucket.set_time_status(&deps.storage, &ghash, time, status)?;
+                updated += 1;
+            }
+            for stay in cell.stays {
+                bucket.set_stay_status(&deps.storage, &ghash, stay, status)?;
+                updated += 1;
+            }
+        }
+        bucket.store(&mut deps.storage)?;
+    }
+
+    store_contributor_status(&mut deps.storage, &contributor, status)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("updated", updated)],
+        data: None,
+    })
+}
+
+/// Remove all the data points of a contributor from the buckets and the hot spot tracker.
+///
+/// Buckets which were already cleared no longer have the contributor's data, so they are simply
+/// skipped. Buckets which expired but were not cleared yet still have their data counted in the
+/// hot spot tracker, so the data is removed from those as well.
+pub fn delete_contributor<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    contributor: String,
+) -> StdResult<HandleResponse> {
+    let status = contributor_status(&deps.storage, &contributor)?;
+    let mut hotspots = HotspotMap::load(&deps.storage)?;
+
+    let mut removed: u64 = 0;
+    for id in ContributorBuckets::load(&deps.storage, &contributor)?.0 {
+        let cells = Contribution::load(&deps.storage, &contributor, &id)?.0;
+        let mut bucket = DailyBucket::new(id);
+        for (ghash, cell) in cells {
+            for time in cell.times {
+                if status == InfectionStatus::Suspected {
+                    bucket.set_time_status(
+                        &deps.storage,
+                        &ghash,
+                        time,
+                        InfectionStatus::Confirmed,
+                    )?;
+                }
+                if bucket.remove_data_point(&deps.storage, &ghash, time)? {
+                    hotspots.remove_data_point(&ghash);
+                    removed += 1;
+                }
+            }
+            for (time, position) in cell.positions {
+                bucket.remove_position(&deps.storage, &ghash, time, position)?;
+            }
+            for stay in cell.stays {
+                if status == InfectionStatus::Suspected {
+                    bucket.set_stay_status(
+                        &deps.storage,
+                        &ghash,
+                        stay,
+                        InfectionStatus::Confirmed,
+                    )?;
+                }
+                if bucket.remove_stay(&deps.storage, &ghash, &stay)? {
+                    hotspots.remove_data_point(&ghash);
+                    removed += 1;
+                }
+            }
+        }
+        bucket.store(&mut deps.storage)?;
+
+        Contribution::remove(&mut deps.storage, &contributor, &id);
+        let mut contributors = BucketContributors::load(&deps.storage, &id)?;
+        contributors.0.retain(|c| c != &contributor);
+        contributors.store(&mut deps.storage, &id)?;
+    }
+
+    ContributorBuckets::remove(&mut deps.storage, &contributor);
+    store_contributor_status(&mut deps.storage, &contributor, InfectionStatus::default())?;
+
+    // removing data may have made other zones more active than the ones we were tracking
+    hotspots.recalculate_hotzones();
+    hotspots.store(&mut deps.storage)?;
+    HotSpots(hotspots.get_top_hotspots()).store(&mut deps.storage)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("removed", removed)],
+        data: None,
+    })
+}
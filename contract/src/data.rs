@@ -1,106 +1,539 @@
-use std::cmp::Ordering;
+use std::cmp::{max, min, Ordering};
 use std::convert::TryInto;
 
 use cosmwasm_std::{
-    to_binary, Api, Env, Extern, HandleResponse, Querier, QueryResult, StdResult, Storage,
+    log, to_binary, Api, Binary, Env, Extern, HandleResponse, Querier, QueryResult, StdError,
+    StdResult, Storage,
 };
 
-use crate::bucket::{load_all_buckets, BucketName, DailyBucket};
-use crate::geohash::GeoLocationTime;
+use crate::binary::binary_data_points;
+use crate::bucket::{BucketId, DailyBucket, Proximity, Stay};
+use crate::contributor::{contributor_status, Contributions};
+use crate::geohash::{GeoLocationStay, GeoLocationTime, Position, SearchRadius};
+use crate::geojson::geojson_data_points;
+use crate::gpx::gpx_data_points;
 use crate::hotspotmap::{HotSpots, HotspotMap};
-use crate::msg::{GoogleLocation, GoogleTakeoutHistory, HotSpot, QueryAnswer};
-use crate::pointer::Pointers;
+use crate::msg::{
+    CompactCell, GoogleLocation, GoogleSemanticHistory, GoogleTakeoutHistory, HotSpot,
+    InfectionStatus, LocationInterval, MatchWindow, OverlapTimes, QueryAnswer, StayPoints,
+};
+use crate::pointer::{Pointers, ONE_DAY};
+use crate::semantic::semantic_data_points;
+use crate::state::config_read;
+use crate::stay::{detect_stays, merge_stays};
 use std::collections::HashMap;
 
 pub const OVERLAP_TIME: u64 = 1000 * 60 * 5;
+/// Upper limit for each side of a `MatchWindow`
+pub const MAX_MATCH_WINDOW: u64 = ONE_DAY;
+/// Default for `MatchLimits::max_radius`, in meters
+pub const DEFAULT_MAX_RADIUS: u32 = 100;
 
 pub fn import_location_data<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     _env: Env,
     data_points: GoogleTakeoutHistory,
+    contributor: Option<String>,
+    stay_points: Option<StayPoints>,
+) -> StdResult<HandleResponse> {
+    let pointers = Pointers::load(&deps.storage)?;
+    let max_accuracy = config_read(&deps.storage).load()?.max_accuracy;
+
+    let mut in_window: Vec<GoogleLocation> = Vec::with_capacity(data_points.locations.len());
+    let mut dropped: u64 = 0;
+    for dp in data_points.locations {
+        // fixes with a large accuracy radius (e.g. from cell towers) would cause false positives
+        if !dp.is_accurate(max_accuracy) {
+            dropped += 1;
+            continue;
+        }
+        // If the data point is dated after or before our two week window, just ignore it.
+        // Most of these should be handled in pre-processing
+        if pointers.find_bucket(dp.timestamp_ms()?).is_some() {
+            in_window.push(dp);
+        }
+    }
+
+    // convert to our internal structure (geohash + time), collapsing stays if we were asked to
+    let (geopts, stays) = match stay_points {
+        Some(config) => detect_stays(in_window, &config)?,
+        None => {
+            let mut geopts: Vec<GeoLocationTime> = Vec::with_capacity(in_window.len());
+            for dp in in_window {
+                geopts.push(dp.try_into()?);
+            }
+            (geopts, vec![])
+        }
+    };
+
+    let imported = insert_data(&mut deps.storage, geopts, stays, contributor.as_deref())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("imported", imported), log("dropped", dropped)],
+        data: None,
+    })
+}
+
+pub fn import_semantic_data<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    data: GoogleSemanticHistory,
+    contributor: Option<String>,
+) -> StdResult<HandleResponse> {
+    let pointers = Pointers::load(&deps.storage)?;
+    let from = pointers.first().map(|p| p.start_time).unwrap_or_default();
+    let to = pointers.last().map(|p| p.end_time).unwrap_or_default();
+
+    let (geopts, stays) = semantic_data_points(data, from, to)?;
+    let imported = insert_data(&mut deps.storage, geopts, stays, contributor.as_deref())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("imported", imported)],
+        data: None,
+    })
+}
+
+pub fn import_gpx_data<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    data: String,
+    contributor: Option<String>,
+) -> StdResult<HandleResponse> {
+    let geopts = gpx_data_points(&data)?;
+    let imported = insert_data_points(&mut deps.storage, geopts, contributor.as_deref())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("imported", imported)],
+        data: None,
+    })
+}
+
+pub fn import_geojson_data<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    data: String,
+    contributor: Option<String>,
+) -> StdResult<HandleResponse> {
+    let geopts = geojson_data_points(&data)?;
+    let imported = insert_data_points(&mut deps.storage, geopts, contributor.as_deref())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("imported", imported)],
+        data: None,
+    })
+}
+
+pub fn import_compact_data<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    cells: Vec<CompactCell>,
+    contributor: Option<String>,
 ) -> StdResult<HandleResponse> {
+    let mut geopts: Vec<GeoLocationTime> = vec![];
+    for cell in cells {
+        geopts.extend(cell.data_points()?);
+    }
+    let imported = insert_data_points(&mut deps.storage, geopts, contributor.as_deref())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("imported", imported)],
+        data: None,
+    })
+}
+
+pub fn import_binary_data<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    data: Binary,
+    contributor: Option<String>,
+) -> StdResult<HandleResponse> {
+    let geopts = binary_data_points(data.as_slice())?;
+    let imported = insert_data_points(&mut deps.storage, geopts, contributor.as_deref())?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("imported", imported)],
+        data: None,
+    })
+}
+
+/// Insert data points, which were already converted to our internal structure, into the buckets
+/// and the hot spot tracker. See `insert_data`
+pub fn insert_data_points<S: Storage>(
+    store: &mut S,
+    data_points: Vec<GeoLocationTime>,
+    contributor: Option<&str>,
+) -> StdResult<u64> {
+    insert_data(store, data_points, vec![], contributor)
+}
+
+/// Insert data points and stays into the buckets and the hot spot tracker. Data which is outside
+/// of our window is ignored. Stays which cross the boundary between buckets are split, so every
+/// bucket only holds its own part of the stay. Returns the amount of entries that were inserted
+///
+/// If a contributor is set, the inserted data is also added to their `Contribution`, so it can be
+/// removed later on
+pub fn insert_data<S: Storage>(
+    store: &mut S,
+    data_points: Vec<GeoLocationTime>,
+    stays: Vec<GeoLocationStay>,
+    contributor: Option<&str>,
+) -> StdResult<u64> {
     // Generally speaking handles are pretty long - this should be acceptable, since they are
     // done once (per day), and there is a lot of processing done at this stage to ensure query
     // times are as low as possible, since that is what the responsiveness of a system which uses
     // this data would be based on
-    let pointers = Pointers::load(&deps.storage)?;
+    let pointers = Pointers::load(store)?;
 
-    // Load all the buckets already, since we assume we will be inserting a large amount of data
-    // (can be optimized to lazy-load each bucket)
-    let mut buckets = load_all_buckets(&deps.storage)?;
+    // Buckets lazy-load only the cells we insert data into, so we only have to read and rewrite
+    // the cells that this import touches
+    let mut buckets: HashMap<BucketId, DailyBucket> = HashMap::default();
 
     // this structure stores geohashes with less accuracy, as well as the amount of times that
     // a specific hash has been seen. The data structure also maintains a list of the top most
     // inserted keys. That way we end up with the top hot spots automatically at the end of the
     // insertion.
-    let mut hotspot_map = HotspotMap::load(&deps.storage)?;
+    let mut hotspot_map = HotspotMap::load(store)?;
 
-    for dp in data_points.locations {
-        // If the data point is dated after or before our two week window, just ignore it.
-        // Most of these should be handled in pre-processing
-        if let Some(bucket) = pointers.find_bucket(dp.timestampMs.u128() as u64) {
-            // convert to our internal structure (geohash + time)
-            let geopt: GeoLocationTime = dp.try_into()?;
+    let mut contributions = contributor.map(Contributions::new);
+    // data without a contributor always belongs to a confirmed case
+    let status = match contributor {
+        Some(c) => contributor_status(store, c)?,
+        None => InfectionStatus::default(),
+    };
+
+    // positions are only kept by contracts that match by distance
+    let keep_positions = config_read(store).load()?.match_distance.is_some();
+
+    // the data points of every cell are inserted at once, see `DailyBucket::insert_data_points`
+    let mut cells: HashMap<(BucketId, String), (Vec<u64>, Vec<(u64, Position)>)> =
+        HashMap::default();
+
+    let mut inserted: u64 = 0;
+    for mut geopt in data_points {
+        if !keep_positions {
+            geopt.position = None;
+        }
 
+        if let Some(bucket) = pointers.find_bucket(geopt.timestamp_ms) {
             // insert data into our hot spot tracker - we only need the hash for this,
             // not the timepoint
             hotspot_map.insert_data_point(geopt.geohash.clone());
 
-            // insert data into time-space tracker.
-            buckets.get_mut(&bucket).unwrap().insert_data_point(geopt);
+            if let Some(c) = contributions.as_mut() {
+                c.bucket(store, bucket)?
+                    .insert(&geopt.geohash, geopt.timestamp_ms, geopt.position);
+            }
+
+            let (times, positions) = cells.entry((bucket, geopt.geohash)).or_default();
+            times.push(geopt.timestamp_ms);
+            if let Some(position) = geopt.position {
+                positions.push((geopt.timestamp_ms, position));
+            }
+            inserted += 1;
+        }
+    }
+
+    // insert data into time-space tracker.
+    for ((bucket, ghash), (times, positions)) in cells {
+        buckets
+            .entry(bucket)
+            .or_insert_with(|| DailyBucket::new(bucket))
+            .insert_data_points(store, &ghash, times, positions, status)?;
+    }
+
+    for geostay in merge_stays(stays) {
+        for pointer in pointers.overlapping(geostay.start, geostay.end) {
+            let stay = Stay {
+                start: max(geostay.start, pointer.start_time),
+                end: min(geostay.end, pointer.end_time),
+            };
+
+            hotspot_map.insert_data_point(geostay.geohash.clone());
+
+            if let Some(c) = contributions.as_mut() {
+                c.bucket(store, pointer.bucket)?
+                    .insert_stay(&geostay.geohash, stay);
+            }
+
+            let daily = buckets
+                .entry(pointer.bucket)
+                .or_insert_with(|| DailyBucket::new(pointer.bucket));
+            if status != InfectionStatus::default() {
+                daily.set_stay_status(store, &geostay.geohash, stay, status)?;
+            }
+            daily.insert_stay(store, &geostay.geohash, stay)?;
+            inserted += 1;
         }
     }
 
     // we extract the top hotspots now, so we can directly query it
     let hotspot_cache = HotSpots(hotspot_map.get_top_hotspots());
 
-    // store all buckets
-    for (name, b) in buckets {
-        b.store(&mut deps.storage, &name)?;
+    // store all modified cells
+    for b in buckets.values() {
+        b.store(store)?;
     }
 
-    hotspot_map.store(&mut deps.storage)?;
+    hotspot_map.store(store)?;
+
+    hotspot_cache.store(store)?;
 
-    hotspot_cache.store(&mut deps.storage)?;
+    if let Some(contributions) = contributions {
+        contributions.store(store)?;
+    }
 
-    // no need to return any special response
-    Ok(HandleResponse::default())
+    Ok(inserted)
 }
 
-pub fn match_data_point<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
+/// Collect the data points of a match query, from all the formats they were given in. Points
+/// which are not accurate enough are left out, the same as when importing
+pub fn query_data_points<S: Storage>(
+    store: &S,
     data_points: Vec<GoogleLocation>,
-) -> QueryResult {
-    let pointers = Pointers::load(&deps.storage)?;
-    let mut geo_overlap: Vec<GeoLocationTime> = Vec::default();
-
-    // loading each bucket at 4 mil data points takes about ~4 seconds, so we cache results to
-    // not read from disk and decrypt twice
-    let mut bucket_cache: HashMap<BucketName, DailyBucket> = HashMap::default();
+    gpx: Option<String>,
+    geojson: Option<String>,
+) -> StdResult<Vec<GeoLocationTime>> {
+    let max_accuracy = config_read(store).load()?.max_accuracy;
 
+    let mut geopts: Vec<GeoLocationTime> = Vec::with_capacity(data_points.len());
     for dp in data_points {
-        if let Some(bucket_name) = pointers.find_bucket(dp.timestampMs.u128() as u64) {
-            if !bucket_cache.contains_key(&bucket_name) {
-                let bucket = DailyBucket::load(&deps.storage, &bucket_name)?;
-                bucket_cache.insert(bucket_name.clone(), bucket);
+        if dp.is_accurate(max_accuracy) {
+            geopts.push(dp.try_into()?);
+        }
+    }
+    if let Some(gpx) = gpx {
+        geopts.extend(gpx_data_points(&gpx)?);
+    }
+    if let Some(geojson) = geojson {
+        geopts.extend(geojson_data_points(&geojson)?);
+    }
+
+    Ok(geopts)
+}
+
+/// The time window of a match query - the one given with the query, as long as it is within the
+/// contract's limits, or the contract's default
+pub fn query_match_window<S: Storage>(
+    store: &S,
+    window: Option<MatchWindow>,
+) -> StdResult<MatchWindow> {
+    let state = config_read(store).load()?;
+    match window {
+        Some(window) => {
+            let limits = state.match_limits.unwrap_or_default();
+            if window.before > limits.max_window || window.after > limits.max_window {
+                return Err(StdError::generic_err(format!(
+                    "Match window can not be longer than {} milliseconds",
+                    limits.max_window
+                )));
             }
+            Ok(window)
+        }
+        None => Ok(state.match_window.unwrap_or_default()),
+    }
+}
+
+/// How far to search around every data point of a query, so that everything within `radius`
+/// meters (or the contract's match distance) is covered. Other queries search the cell of the
+/// data point and its neighbors
+pub fn query_search_radius<S: Storage>(store: &S, radius: Option<u32>) -> StdResult<SearchRadius> {
+    let state = config_read(store).load()?;
+    let radius = match (radius, state.match_distance) {
+        (Some(radius), _) => radius,
+        (None, Some(distance)) => return Ok(SearchRadius::Meters(distance)),
+        (None, None) => return Ok(SearchRadius::Neighbors),
+    };
+
+    let limits = state.match_limits.unwrap_or_default();
+    if radius > limits.max_radius {
+        return Err(StdError::generic_err(format!(
+            "Match radius can not be larger than {} meters",
+            limits.max_radius
+        )));
+    }
+
+    Ok(SearchRadius::Meters(radius))
+}
+
+/// How close (in meters) stored data points with a position have to be to a query to match it.
+/// Only contracts which store positions check the distance, and the query's radius replaces the
+/// contract's match distance
+pub fn query_distance<S: Storage>(store: &S, radius: Option<u32>) -> StdResult<Option<u32>> {
+    let match_distance = config_read(store).load()?.match_distance;
+    Ok(match_distance.map(|distance| radius.unwrap_or(distance)))
+}
+
+pub fn proximity(geoloc: &GeoLocationTime, distance: Option<u32>) -> Option<Proximity> {
+    match (geoloc.position, distance) {
+        (Some(position), Some(distance)) => Some(Proximity { position, distance }),
+        _ => None,
+    }
+}
+
+/// Whether each of the data points overlaps with data stored in the contract
+pub fn matched_data_points<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    data_points: &[GeoLocationTime],
+    window: &MatchWindow,
+    radius: SearchRadius,
+    distance: Option<u32>,
+) -> StdResult<Vec<bool>> {
+    let pointers = Pointers::load(&deps.storage)?;
+    let mut matched: Vec<bool> = Vec::with_capacity(data_points.len());
+
+    // buckets only read the cells (geohash + neighbors) that we check, and cache them so we do not
+    // read from disk and decrypt the same cell twice
+    let mut bucket_cache: HashMap<BucketId, DailyBucket> = HashMap::default();
+
+    for geoloc in data_points {
+        let mut found = false;
+        let area = radius.area(geoloc)?;
+
+        // the window may reach into the buckets before and after the one of the data point
+        let (from, to) = window.bounds(geoloc.timestamp_ms, geoloc.timestamp_ms);
+        for pointer in pointers.overlapping(from, to) {
+            let bucket = bucket_cache
+                .entry(pointer.bucket)
+                .or_insert_with(|| DailyBucket::new(pointer.bucket));
 
-            let geoloc: GeoLocationTime = dp.try_into()?;
             // matches according to geohash and time
-            if bucket_cache.get(&bucket_name).unwrap().match_pos(
+            let near = proximity(geoloc, distance);
+            if bucket.match_pos(
+                &deps.storage,
                 &geoloc.geohash,
+                &area,
                 geoloc.timestamp_ms,
-                OVERLAP_TIME,
+                window,
+                near,
             )? {
-                geo_overlap.push(geoloc);
+                found = true;
+                break;
             }
         }
+        matched.push(found);
     }
+
+    Ok(matched)
+}
+
+pub fn match_data_point<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    data_points: Vec<GeoLocationTime>,
+    window: MatchWindow,
+    radius: SearchRadius,
+    distance: Option<u32>,
+) -> QueryResult {
+    let matched = matched_data_points(deps, &data_points, &window, radius, distance)?;
+    let geo_overlap: Vec<GeoLocationTime> = data_points
+        .into_iter()
+        .zip(matched)
+        .filter(|(_, matched)| *matched)
+        .map(|(geoloc, _)| geoloc)
+        .collect();
+
     to_binary(&QueryAnswer::Overlap {
         data_points: geo_overlap,
     })
 }
 
+pub fn match_data_point_times<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    data_points: Vec<GeoLocationTime>,
+    window: MatchWindow,
+    radius: SearchRadius,
+    distance: Option<u32>,
+) -> QueryResult {
+    let pointers = Pointers::load(&deps.storage)?;
+    let mut matches: Vec<OverlapTimes> = Vec::default();
+
+    let mut bucket_cache: HashMap<BucketId, DailyBucket> = HashMap::default();
+
+    for geoloc in data_points {
+        let mut times: Vec<u64> = vec![];
+        let mut stays: Vec<Stay> = vec![];
+        let area = radius.area(&geoloc)?;
+
+        // pointers are sorted, so the times (and stays) we find stay sorted as well
+        let (from, to) = window.bounds(geoloc.timestamp_ms, geoloc.timestamp_ms);
+        for pointer in pointers.overlapping(from, to) {
+            let bucket = bucket_cache
+                .entry(pointer.bucket)
+                .or_insert_with(|| DailyBucket::new(pointer.bucket));
+
+            let (found_times, found_stays) = bucket.overlapping_times(
+                &deps.storage,
+                &geoloc.geohash,
+                &area,
+                geoloc.timestamp_ms,
+                &window,
+                proximity(&geoloc, distance),
+            )?;
+            times.extend(found_times);
+            stays.extend(found_stays);
+        }
+
+        if !times.is_empty() || !stays.is_empty() {
+            matches.push(OverlapTimes {
+                data_point: geoloc,
+                times,
+                stays,
+            });
+        }
+    }
+    to_binary(&QueryAnswer::OverlapTimes { matches })
+}
+
+pub fn match_intervals<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    intervals: Vec<LocationInterval>,
+    window: MatchWindow,
+    radius: SearchRadius,
+    distance: Option<u32>,
+) -> QueryResult {
+    let pointers = Pointers::load(&deps.storage)?;
+    let mut geo_overlap: Vec<GeoLocationStay> = Vec::default();
+
+    let mut bucket_cache: HashMap<BucketId, DailyBucket> = HashMap::default();
+
+    for interval in intervals {
+        // search around the interval the same way as around a data point at the same place
+        let (latitude_e7, longitude_e7) = (interval.latitudeE7, interval.longitudeE7);
+        let geostay: GeoLocationStay = interval.try_into()?;
+        let point = GeoLocationTime::from_e7(latitude_e7, longitude_e7, geostay.start)?;
+        let area = radius.area(&point)?;
+
+        // an interval may span several buckets, as well as the window around it
+        let (from, to) = window.bounds(geostay.start, geostay.end);
+        for pointer in pointers.overlapping(from, to) {
+            let bucket = bucket_cache
+                .entry(pointer.bucket)
+                .or_insert_with(|| DailyBucket::new(pointer.bucket));
+
+            if bucket.match_interval(
+                &deps.storage,
+                &geostay.geohash,
+                &area,
+                geostay.start,
+                geostay.end,
+                &window,
+                proximity(&point, distance),
+            )? {
+                geo_overlap.push(geostay);
+                break;
+            }
+        }
+    }
+    to_binary(&QueryAnswer::IntervalOverlap {
+        intervals: geo_overlap,
+    })
+}
+
 impl PartialOrd for HotSpot {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -131,18 +564,3 @@ impl ToString for HotSpot {
         return format!("{} : {}", self.geo_location, self.power);
     }
 }
-
-// pub const DISTANCE: f64 = 10.0; // in meters
-// pub const EARTH_RADIUS: f64 = 6371000.0; // in meters
-// fn match_location(e: &GeoLocationTime, d: &GeoLocationTime) -> bool {
-//     if (e.lat - d.lat).abs() * 111000.0 < DISTANCE * 0.71 {
-//         // then we can run a more computationally expensive and precise comparison
-//         if (e.lat.sin() * d.lat.sin() + e.lat.cos() * d.lat.cos() * (e.lng - d.lng).cos()).acos()
-//             * EARTH_RADIUS
-//             < DISTANCE
-//         {
-//             return true;
-//         }
-//     }
-//     false
-// }
@@ -0,0 +1,164 @@
+use std::cmp::min;
+
+use bincode2;
+use cosmwasm_std::{
+    log, to_binary, Api, Env, Extern, HandleResponse, Querier, QueryResult, ReadonlyStorage,
+    StdError, StdResult, Storage,
+};
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+use serde::{Deserialize, Serialize};
+
+use crate::bucket::{BucketId, CellCount, DailyBucket, SortedIndex, SHARD_LIST};
+use crate::contributor::expire_contributions;
+use crate::hotspotmap::{HotSpots, HotspotMap};
+use crate::msg::QueryAnswer;
+use crate::session::expire_sessions;
+
+pub static EXPIRY_KEY: &[u8] = b"expiry";
+
+/// Default amount of cells which are cleared in a single call
+pub const DEFAULT_EXPIRY_LIMIT: u32 = 10000;
+
+/// `ExpiryQueue` holds the buckets which are no longer covered by our pointers, but whose data is
+/// still in storage. Clearing a large bucket can take more gas than fits in a single transaction,
+/// so buckets are cleared a chunk of cells at a time. The cells are found through the index shards
+/// of the bucket (see `SortedIndex`), which are cleared one after the other, in the order of the
+/// shard list, so every step only reads the shards it clears cells from.
+///
+/// Since expired buckets are removed from the pointers before they are queued, imports and queries
+/// never touch them while they are being cleared. Their data points are still counted in the hot
+/// spot tracker until they are cleared, though.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ExpiryQueue {
+    /// expired buckets, oldest first
+    pub buckets: Vec<BucketId>,
+    /// the amount of cells of the first bucket in the queue which were already cleared
+    pub cursor: u32,
+    /// the position in the shard list of the shard which is being cleared
+    pub shard: u32,
+    /// the amount of cells of that shard which were already cleared
+    pub shard_cursor: u32,
+}
+
+impl ExpiryQueue {
+    pub fn store<S: Storage>(&self, store: &mut S) -> StdResult<()> {
+        let mut config_store = PrefixedStorage::new(EXPIRY_KEY, store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error serializing expiry queue"))?;
+
+        config_store.set(EXPIRY_KEY, &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(store: &S) -> StdResult<Self> {
+        let config_store = ReadonlyPrefixedStorage::new(EXPIRY_KEY, store);
+        if let Some(temp) = config_store.get(EXPIRY_KEY) {
+            let queue: Self = bincode2::deserialize(&temp)
+                .map_err(|_| StdError::generic_err("Error deserializing expiry queue"))?;
+            return Ok(queue);
+        }
+
+        Ok(Self::default())
+    }
+}
+
+/// Clear up to `limit` cells of the expired buckets, and remove their data points from the hot
+/// spot tracker. Returns the amount of cells that were cleared
+pub fn expire_cells<S: Storage>(store: &mut S, limit: u32) -> StdResult<u32> {
+    let mut queue = ExpiryQueue::load(store)?;
+    if queue.buckets.is_empty() {
+        return Ok(0);
+    }
+
+    let mut hotspots = HotspotMap::load(store)?;
+    let mut processed: u32 = 0;
+
+    while processed < limit && !queue.buckets.is_empty() {
+        let id = queue.buckets[0];
+        let shards = SortedIndex::load(store, &id, SHARD_LIST)?;
+
+        while processed < limit && (queue.shard as usize) < shards.0.len() {
+            let prefix = &shards.0[queue.shard as usize];
+            let shard = SortedIndex::load(store, &id, prefix)?;
+
+            let start = queue.shard_cursor as usize;
+            let end = min(shard.0.len(), start + (limit - processed) as usize);
+
+            for ghash in &shard.0[start..end] {
+                if let Some(cell) = DailyBucket::load_cell(store, &id, ghash)? {
+                    for _ in 0..cell.len() {
+                        hotspots.remove_data_point(ghash)
+                    }
+                }
+                DailyBucket::remove_cell(store, &id, ghash);
+            }
+            processed += (end - start) as u32;
+            queue.cursor += (end - start) as u32;
+
+            if end == shard.0.len() {
+                SortedIndex::remove(store, &id, prefix);
+                queue.shard += 1;
+                queue.shard_cursor = 0;
+            } else {
+                queue.shard_cursor = end as u32;
+            }
+        }
+
+        if queue.shard as usize == shards.0.len() {
+            // done with this bucket
+            CellCount::remove(store, &id);
+            expire_contributions(store, &id)?;
+            SortedIndex::remove(store, &id, SHARD_LIST);
+            queue.buckets.remove(0);
+            queue.cursor = 0;
+            queue.shard = 0;
+            queue.shard_cursor = 0;
+        }
+    }
+
+    queue.store(store)?;
+
+    // removing data may have made other zones more active than the ones we were tracking
+    hotspots.recalculate_hotzones();
+    hotspots.store(store)?;
+    HotSpots(hotspots.get_top_hotspots()).store(store)?;
+
+    Ok(processed)
+}
+
+pub fn process_expiry<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let expired = expire_cells(&mut deps.storage, limit.unwrap_or(DEFAULT_EXPIRY_LIMIT))?;
+    let pending = ExpiryQueue::load(&deps.storage)?.buckets.len();
+    let expired_sessions = expire_sessions(&mut deps.storage, env.block.time * 1000)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("expired_cells", expired),
+            log("pending_buckets", pending),
+            log("expired_sessions", expired_sessions),
+        ],
+        data: None,
+    })
+}
+
+pub fn query_expiry_status<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> QueryResult {
+    let queue = ExpiryQueue::load(&deps.storage)?;
+
+    let mut remaining_cells: u64 = 0;
+    for id in &queue.buckets {
+        remaining_cells += CellCount::load(&deps.storage, id)?.0 as u64;
+    }
+    remaining_cells -= queue.cursor as u64;
+
+    to_binary(&QueryAnswer::ExpiryStatus {
+        pending_buckets: queue.buckets.len() as u32,
+        processed_cells: queue.cursor,
+        remaining_cells,
+    })
+}
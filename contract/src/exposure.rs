@@ -0,0 +1,121 @@
+use cosmwasm_std::{to_binary, Api, Extern, Querier, QueryResult, Storage};
+
+use crate::data::matched_data_points;
+use crate::geohash::{GeoLocationTime, SearchRadius};
+use crate::msg::{DailyExposure, MatchWindow, QueryAnswer};
+use crate::pointer::ONE_DAY;
+use crate::stay::DEFAULT_MAX_GAP;
+
+const ONE_MINUTE: u64 = 1000 * 60;
+
+/// A run of consecutive matched data points, from the time of the first one to the last one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Episode {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Episode {
+    /// The estimated duration of the episode, in whole minutes. An episode of a single data point
+    /// still means we were at the same place at the same time, so it counts as a minute
+    pub fn minutes(&self) -> u64 {
+        ((self.end - self.start + ONE_MINUTE - 1) / ONE_MINUTE).max(1)
+    }
+}
+
+/// Merge matched data points into episodes. Data points are sorted by time, and consecutive matched
+/// points belong to the same episode, unless there is a data point which did not match between
+/// them, or they are more than `DEFAULT_MAX_GAP` apart - in which case we don't know what happened
+/// in between
+pub fn exposure_episodes(data_points: Vec<(u64, bool)>) -> Vec<Episode> {
+    let matches = data_points
+        .into_iter()
+        .map(|(time, matched)| (time, if matched { Some(()) } else { None }));
+
+    group_episodes(matches.collect())
+        .into_iter()
+        .map(|(episode, _)| episode)
+        .collect()
+}
+
+/// Same as `exposure_episodes`, for data points which carry some details about their match. The
+/// details of all the data points of an episode are returned with it
+pub fn group_episodes<T>(mut data_points: Vec<(u64, Option<T>)>) -> Vec<(Episode, Vec<T>)> {
+    data_points.sort_by_key(|(time, _)| *time);
+
+    let mut episodes: Vec<(Episode, Vec<T>)> = vec![];
+    let mut current: Option<(Episode, Vec<T>)> = None;
+    for (time, matched) in data_points {
+        current = match (current, matched) {
+            (Some((episode, mut details)), Some(matched))
+                if time - episode.end <= DEFAULT_MAX_GAP =>
+            {
+                details.push(matched);
+                Some((
+                    Episode {
+                        start: episode.start,
+                        end: time,
+                    },
+                    details,
+                ))
+            }
+            (previous, Some(matched)) => {
+                episodes.extend(previous);
+                Some((
+                    Episode {
+                        start: time,
+                        end: time,
+                    },
+                    vec![matched],
+                ))
+            }
+            (previous, None) => {
+                episodes.extend(previous);
+                None
+            }
+        };
+    }
+    episodes.extend(current);
+
+    episodes
+}
+
+/// Sum up the episodes of every day. Episodes are counted in the (UTC) day they started in
+pub fn daily_exposure(episodes: &[Episode]) -> Vec<DailyExposure> {
+    let mut days: Vec<DailyExposure> = vec![];
+    for episode in episodes {
+        let day = episode.start - episode.start % ONE_DAY;
+        if days.last().map(|d| d.day) != Some(day) {
+            days.push(DailyExposure {
+                day,
+                minutes: 0,
+                episodes: 0,
+                longest_episode: 0,
+            });
+        }
+
+        // episodes are sorted, so this is always the day of the episode
+        let summary = days.last_mut().unwrap();
+        summary.minutes += episode.minutes();
+        summary.episodes += 1;
+        summary.longest_episode = summary.longest_episode.max(episode.minutes());
+    }
+
+    days
+}
+
+/// Summarize how long the data points overlapped with data stored in the contract, per day
+pub fn exposure_summary<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    data_points: Vec<GeoLocationTime>,
+    window: MatchWindow,
+    radius: SearchRadius,
+    distance: Option<u32>,
+) -> QueryResult {
+    let matched = matched_data_points(deps, &data_points, &window, radius, distance)?;
+    let times = data_points.iter().map(|dp| dp.timestamp_ms).zip(matched);
+
+    to_binary(&QueryAnswer::ExposureSummary {
+        days: daily_exposure(&exposure_episodes(times.collect())),
+    })
+}
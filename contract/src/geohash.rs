@@ -1,17 +1,47 @@
+use std::collections::HashSet;
+
 use cosmwasm_std::{StdError, StdResult};
-use geohash::{encode, Coordinate};
+use geohash::{decode, encode, Coordinate};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-const PRECISION: usize = 9usize;
+pub const PRECISION: usize = 9usize;
+
+/// in meters
+pub const EARTH_RADIUS: f64 = 6371000.0;
+
+/// the largest amount of rings that `neighborhood` will search - 4 rings are 80 cells
+pub const MAX_RINGS: u32 = 4;
+
+/// stored positions are in units of 10^-5 degrees (E7 / 100), which is about a meter
+pub const POSITION_SCALE: i64 = 100;
+
+/// the characters used by geohashes, in the order of the values they encode
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
 
 /// return the geohash to a precision degree specified by `PRECISION`.
 /// 7 ~ 76m
 /// 8 ~ 20m
 /// 9 ~ 7m
 /// 10 ~ 1m
+///
+/// `x` is the longitude, and must be between -180 and 180. `y` is the latitude, and must be
+/// between -90 and 90
 pub fn ghash(x: f64, y: f64) -> StdResult<String> {
+    if !(-90.0..=90.0).contains(&y) {
+        return Err(StdError::generic_err(format!(
+            "Latitude {} is out of range. Must be between -90 and 90",
+            y
+        )));
+    }
+    if !(-180.0..=180.0).contains(&x) {
+        return Err(StdError::generic_err(format!(
+            "Longitude {} is out of range. Must be between -180 and 180",
+            x
+        )));
+    }
+
     encode(
         Coordinate {
             x, // lng
@@ -22,6 +52,168 @@ pub fn ghash(x: f64, y: f64) -> StdResult<String> {
     .map_err(|_| StdError::generic_err(format!("Cannot encode data to geohash ({}, {})", x, y)))
 }
 
+/// Same as `ghash`, for coordinates in the E7 format used by Google (degrees multiplied by 10^7)
+pub fn ghash_e7(latitude_e7: i64, longitude_e7: i64) -> StdResult<String> {
+    ghash(longitude_e7 as f64 / 1e7, latitude_e7 as f64 / 1e7)
+}
+
+/// Make sure a geohash that was computed outside of the contract has our precision, and only
+/// uses valid characters
+pub fn validate_geohash(geohash: &str) -> StdResult<()> {
+    if geohash.len() != PRECISION {
+        return Err(StdError::generic_err(format!(
+            "Geohash {} must be exactly {} characters long",
+            geohash, PRECISION
+        )));
+    }
+    if let Some(c) = geohash.bytes().find(|c| !BASE32.contains(c)) {
+        return Err(StdError::generic_err(format!(
+            "Geohash {} contains an invalid character: {}",
+            geohash, c as char
+        )));
+    }
+
+    Ok(())
+}
+
+/// Convert a geohash in its integer form (5 bits per character, first character in the most
+/// significant bits) to a string
+pub fn geohash_from_int(value: u64) -> StdResult<String> {
+    if value >= 1 << (5 * PRECISION) {
+        return Err(StdError::generic_err(format!(
+            "Integer geohash {} is larger than {} bits",
+            value,
+            5 * PRECISION
+        )));
+    }
+
+    Ok((0..PRECISION)
+        .rev()
+        .map(|i| BASE32[((value >> (5 * i)) & 0x1f) as usize] as char)
+        .collect())
+}
+
+/// Great-circle distance in meters between two coordinates in the E7 format, using the haversine
+/// formula
+pub fn distance_e7(lat1_e7: i64, lng1_e7: i64, lat2_e7: i64, lng2_e7: i64) -> f64 {
+    let to_radians = |e7: i64| (e7 as f64 / 1e7).to_radians();
+    let (lat1, lat2) = (to_radians(lat1_e7), to_radians(lat2_e7));
+    let d_lat = lat2 - lat1;
+    let d_lng = to_radians(lng2_e7) - to_radians(lng1_e7);
+
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// in meters, along a meridian
+const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// The length of the shorter side of a geohash cell at `latitude` (in degrees), in meters. Cells
+/// have the same height everywhere, but get narrower towards the poles
+pub fn cell_size(precision: usize, latitude: f64) -> f64 {
+    // geohash bits alternate between longitude and latitude, starting with longitude
+    let bits = 5 * precision as i32;
+    let lat_bits = bits / 2;
+    let height = 180.0 / 2f64.powi(lat_bits) * METERS_PER_DEGREE;
+    let width = 360.0 / 2f64.powi(bits - lat_bits) * METERS_PER_DEGREE;
+    height.min(width * latitude.to_radians().cos())
+}
+
+/// The area that is searched around a data point: its cell at `precision`, and `rings` rings of
+/// cells around it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchArea {
+    pub precision: usize,
+    pub rings: u32,
+}
+
+impl Default for SearchArea {
+    /// the cell of the data point, and its 8 neighbors
+    fn default() -> Self {
+        Self {
+            precision: PRECISION,
+            rings: 1,
+        }
+    }
+}
+
+impl SearchArea {
+    /// The smallest area that covers everything within `radius` meters of any point in the center
+    /// cell, at `latitude`. We use the most precise cells we can, as long as we don't need more
+    /// than `MAX_RINGS` rings of them - a few more cells are cheaper to check than much larger ones
+    pub fn for_radius(radius: u32, latitude: f64) -> Self {
+        // size the cells for the edge of the area which is closest to a pole, where they are the
+        // narrowest
+        let latitude = (latitude.abs() + radius as f64 / METERS_PER_DEGREE).min(90.0);
+        for precision in (1..=PRECISION).rev() {
+            let rings = (radius as f64 / cell_size(precision, latitude))
+                .ceil()
+                .max(1.0) as u32;
+            if rings <= MAX_RINGS {
+                return Self { precision, rings };
+            }
+        }
+
+        Self {
+            precision: 1,
+            rings: 1,
+        }
+    }
+}
+
+/// How far around every data point a query searches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchRadius {
+    /// the cell of the data point, and its 8 neighbors
+    Neighbors,
+    /// everything within this many meters of the data point
+    Meters(u32),
+}
+
+impl SearchRadius {
+    /// The area to search around a single data point. Cells are narrower closer to the poles, so
+    /// the same radius needs more of them there
+    pub fn area(&self, geoloc: &GeoLocationTime) -> StdResult<SearchArea> {
+        match self {
+            Self::Neighbors => Ok(SearchArea::default()),
+            Self::Meters(radius) => Ok(SearchArea::for_radius(*radius, geoloc.latitude()?)),
+        }
+    }
+}
+
+/// All the cells in `rings` rings around a geohash (not including the geohash itself), ordered
+/// from the closest ring out. Cells are only returned once, even where the rings wrap around the
+/// poles or the antimeridian
+pub fn neighborhood(geohash: &str, rings: u32) -> StdResult<Vec<String>> {
+    if rings > MAX_RINGS {
+        return Err(StdError::generic_err(format!(
+            "Can not search more than {} rings of cells",
+            MAX_RINGS
+        )));
+    }
+
+    let mut seen: HashSet<String> = HashSet::default();
+    seen.insert(geohash.to_string());
+
+    // every ring is made of the neighbors of the previous ring which we have not seen yet
+    let mut all: Vec<String> = vec![];
+    let mut ring_start = 0;
+    let mut previous: Vec<String> = vec![geohash.to_string()];
+    for _ in 0..rings {
+        for cell in &previous {
+            for n in neighbors(cell)? {
+                if seen.insert(n.clone()) {
+                    all.push(n);
+                }
+            }
+        }
+        previous = all[ring_start..].to_vec();
+        ring_start = all.len();
+    }
+
+    Ok(all)
+}
+
 pub fn neighbors(geohash: &String) -> StdResult<Vec<String>> {
     let mut all: Vec<String> = vec![];
 
@@ -40,8 +232,79 @@ pub fn neighbors(geohash: &String) -> StdResult<Vec<String>> {
     Ok(all)
 }
 
+/// A stay in a single geohash cell, from `start` to `end` (inclusive)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GeoLocationStay {
+    pub geohash: String,
+    pub start: u64,
+    pub end: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GeoLocationTime {
     pub geohash: String,
     pub timestamp_ms: u64,
+    /// the coordinates the geohash was computed from, when we know them. These are only used
+    /// internally, and are never part of a response
+    #[serde(skip)]
+    pub position: Option<Position>,
+}
+
+impl GeoLocationTime {
+    /// A data point at coordinates in the E7 format
+    pub fn from_e7(latitude_e7: i64, longitude_e7: i64, timestamp_ms: u64) -> StdResult<Self> {
+        Ok(Self {
+            geohash: ghash_e7(latitude_e7, longitude_e7)?,
+            timestamp_ms,
+            position: Some(Position::from_e7(latitude_e7, longitude_e7)),
+        })
+    }
+
+    /// The latitude of the data point in degrees, or of the center of its cell if we don't know
+    /// its position
+    pub fn latitude(&self) -> StdResult<f64> {
+        if let Some(position) = self.position {
+            return Ok((position.lat as i64 * POSITION_SCALE) as f64 / 1e7);
+        }
+
+        let (center, _, _) =
+            decode(&self.geohash).map_err(|_| StdError::generic_err("Failed to decode geohash"))?;
+        Ok(center.y)
+    }
+
+    /// A data point at coordinates in degrees. Like `ghash`, takes the longitude first
+    pub fn from_degrees(x: f64, y: f64, timestamp_ms: u64) -> StdResult<Self> {
+        Ok(Self {
+            geohash: ghash(x, y)?,
+            timestamp_ms,
+            position: Some(Position::from_e7(
+                (y * 1e7).round() as i64,
+                (x * 1e7).round() as i64,
+            )),
+        })
+    }
+}
+
+/// Coordinates quantized to `POSITION_SCALE` (about a meter), so they can be stored next to a
+/// timestamp in only 8 bytes
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub lat: i32,
+    pub lng: i32,
+}
+
+impl Position {
+    pub fn from_e7(latitude_e7: i64, longitude_e7: i64) -> Self {
+        let quantize = |e7: i64| ((e7 as f64) / POSITION_SCALE as f64).round() as i32;
+        Self {
+            lat: quantize(latitude_e7),
+            lng: quantize(longitude_e7),
+        }
+    }
+
+    /// Great-circle distance in meters
+    pub fn distance(&self, other: &Position) -> f64 {
+        let e7 = |value: i32| value as i64 * POSITION_SCALE;
+        distance_e7(e7(self.lat), e7(self.lng), e7(other.lat), e7(other.lng))
+    }
 }
@@ -0,0 +1,88 @@
+use cosmwasm_std::{StdError, StdResult};
+use serde::Deserialize;
+
+use crate::geohash::GeoLocationTime;
+use crate::time::parse_rfc3339;
+
+/// The parts of a GeoJSON `FeatureCollection` that we use. Times are read from the feature
+/// properties, using the same names as common GPX to GeoJSON converters: `time` for points, and
+/// `coordTimes` (one for every vertex) for line strings
+#[derive(Deserialize)]
+struct FeatureCollection {
+    #[serde(rename = "type")]
+    kind: String,
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometry,
+    properties: Option<Properties>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Point { coordinates: Vec<f64> },
+    LineString { coordinates: Vec<Vec<f64>> },
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Default)]
+struct Properties {
+    time: Option<String>,
+    coordTimes: Option<Vec<String>>,
+}
+
+/// Convert a GeoJSON `FeatureCollection` of `Point` and `LineString` features to our internal
+/// structure (geohash + time). Every point, and every vertex of a line string, must have a time
+pub fn geojson_data_points(geojson: &str) -> StdResult<Vec<GeoLocationTime>> {
+    let collection: FeatureCollection = serde_json::from_str(geojson)
+        .map_err(|e| StdError::generic_err(format!("Failed to parse GeoJSON: {}", e)))?;
+    if collection.kind != "FeatureCollection" {
+        return Err(StdError::generic_err(format!(
+            "GeoJSON must be a FeatureCollection, got {}",
+            collection.kind
+        )));
+    }
+
+    let mut data_points: Vec<GeoLocationTime> = vec![];
+    for (idx, feature) in collection.features.into_iter().enumerate() {
+        let properties = feature.properties.unwrap_or_default();
+        match feature.geometry {
+            Geometry::Point { coordinates } => {
+                let time = properties.time.ok_or_else(|| {
+                    StdError::generic_err(format!("GeoJSON feature {} has no time", idx))
+                })?;
+                data_points.push(position_to_point(&coordinates, &time)?);
+            }
+            Geometry::LineString { coordinates } => {
+                let times = properties.coordTimes.unwrap_or_default();
+                if times.len() != coordinates.len() {
+                    return Err(StdError::generic_err(format!(
+                        "GeoJSON feature {} has {} coordinates, but {} coordTimes",
+                        idx,
+                        coordinates.len(),
+                        times.len()
+                    )));
+                }
+                for (position, time) in coordinates.iter().zip(times.iter()) {
+                    data_points.push(position_to_point(position, time)?);
+                }
+            }
+        }
+    }
+
+    Ok(data_points)
+}
+
+/// GeoJSON positions are [longitude, latitude], optionally followed by the altitude
+fn position_to_point(position: &[f64], time: &str) -> StdResult<GeoLocationTime> {
+    if position.len() < 2 {
+        return Err(StdError::generic_err(
+            "GeoJSON position must have a longitude and a latitude",
+        ));
+    }
+
+    GeoLocationTime::from_degrees(position[0], position[1], parse_rfc3339(time)?)
+}
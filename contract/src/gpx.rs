@@ -0,0 +1,63 @@
+use cosmwasm_std::{StdError, StdResult};
+use roxmltree::{Document, Node};
+
+use crate::geohash::GeoLocationTime;
+use crate::time::parse_rfc3339;
+
+/// Convert the track points of a GPX document to our internal structure (geohash + time).
+///
+/// Only track points (`trkpt`) are used, since routes and waypoints do not say when someone was
+/// at a location. Every track point must have a `time` - if any of them don't, the whole document
+/// is rejected, and the error says how many points were missing a timestamp
+pub fn gpx_data_points(gpx: &str) -> StdResult<Vec<GeoLocationTime>> {
+    let doc = Document::parse(gpx)
+        .map_err(|e| StdError::generic_err(format!("Failed to parse GPX: {}", e)))?;
+
+    let mut data_points: Vec<GeoLocationTime> = vec![];
+    let mut total: usize = 0;
+    let mut missing_time: usize = 0;
+    let mut first_missing: Option<usize> = None;
+
+    for point in doc.descendants().filter(|n| n.has_tag_name("trkpt")) {
+        total += 1;
+
+        let latitude = coordinate(&point, "lat")?;
+        let longitude = coordinate(&point, "lon")?;
+
+        let time = point
+            .children()
+            .find(|n| n.has_tag_name("time"))
+            .and_then(|n| n.text());
+        match time {
+            Some(time) => data_points.push(GeoLocationTime::from_degrees(
+                longitude,
+                latitude,
+                parse_rfc3339(time.trim())?,
+            )?),
+            None => {
+                missing_time += 1;
+                first_missing.get_or_insert(total);
+            }
+        }
+    }
+
+    if missing_time > 0 {
+        return Err(StdError::generic_err(format!(
+            "{} of {} GPX track points have no timestamp (the first is track point {})",
+            missing_time,
+            total,
+            first_missing.unwrap_or_default()
+        )));
+    }
+
+    Ok(data_points)
+}
+
+fn coordinate(point: &Node, name: &str) -> StdResult<f64> {
+    point
+        .attribute(name)
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .ok_or_else(|| {
+            StdError::generic_err(format!("GPX track point has a missing or invalid {}", name))
+        })
+}
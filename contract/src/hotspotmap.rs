@@ -217,17 +217,39 @@ impl HotspotMap {
         let mut truncated = ghash.clone();
         truncated.truncate(7);
 
-        if self.locations.contains_key(&truncated) {
-            let entry = self.locations.get_mut(&truncated).unwrap();
-            *entry -= 1;
+        if let Some(entry) = self.locations.get_mut(&truncated) {
+            *entry = entry.saturating_sub(1);
+            if *entry == 0 {
+                self.locations.remove(&truncated);
+            }
         }
 
         if let Some(hz) = self._get_mut_hotzone_by_hash(&truncated) {
-            hz.power -= 1;
+            hz.power = hz.power.saturating_sub(1);
             self.hotzones.sort_unstable_by(|a, b| b.cmp(a));
         }
     }
 
+    /// Rebuild the list of hot zones from all the locations we track. Removing data points only
+    /// updates zones which are already in the list, so this should be called after removing data,
+    /// since other zones may now be more active
+    pub fn recalculate_hotzones(&mut self) {
+        let zones = self.hotzones.len();
+
+        let mut all: Vec<HotSpot> = self
+            .locations
+            .iter()
+            .map(|(k, v)| HotSpot {
+                geo_location: k.clone(),
+                power: *v,
+            })
+            .collect();
+        all.sort_unstable_by(|a, b| b.cmp(a));
+        all.resize(zones, HotSpot::default());
+
+        self.hotzones = all;
+    }
+
     pub fn get_top_hotspots(&self) -> Vec<HotSpot> {
         self.hotzones.clone()
     }
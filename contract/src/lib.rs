@@ -1,11 +1,21 @@
+mod binary;
 mod bucket;
 pub mod contract;
+mod contributor;
 mod data;
+mod expiry;
+mod exposure;
 mod geohash;
+mod geojson;
+mod gpx;
 mod hotspotmap;
 pub mod msg;
 pub mod pointer;
+mod risk;
+mod semantic;
+mod session;
 mod state;
+mod stay;
 mod time;
 
 #[cfg(target_arch = "wasm32")]
@@ -1,25 +1,141 @@
 use std::convert::TryInto;
 
-use cosmwasm_std::{HumanAddr, StdError, StdResult, Uint128};
+use cosmwasm_std::{Binary, HumanAddr, StdError, StdResult, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::geohash::{ghash, GeoLocationTime};
+use crate::bucket::Stay;
+use crate::data::{DEFAULT_MAX_RADIUS, MAX_MATCH_WINDOW, OVERLAP_TIME};
+use crate::geohash::{
+    geohash_from_int, ghash_e7, validate_geohash, GeoLocationStay, GeoLocationTime,
+};
+use crate::time::parse_rfc3339;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
     pub start_time: u64,
+    /// The amount of days for which data is kept. Defaults to 14 days
+    pub retention_days: Option<u32>,
+    /// The amount of hours covered by each bucket. Smaller buckets mean less data has to be read
+    /// by each query, and more precise expiry of old data. Must divide 24, defaults to 24 hours
+    pub bucket_hours: Option<u32>,
+    /// Data points with an accuracy radius larger than this many meters are ignored, both when
+    /// importing and when matching. Points without an accuracy are always used. Not set by default
+    pub max_accuracy: Option<u32>,
+    /// How close in time a query has to be to stored data to match it. Defaults to 5 minutes
+    /// before and after
+    pub match_window: Option<MatchWindow>,
+    /// The largest radius and time window that a single query can ask for. Defaults to 100 meters,
+    /// and a day before and after
+    pub match_limits: Option<MatchLimits>,
+    /// If set, data points are stored together with their (rounded) coordinates, and only match
+    /// queries which are within this many meters of them, instead of anywhere in a neighboring
+    /// geohash cell. Not set by default
+    pub match_distance: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
     /// Adds new data to the contract, in the format specified by `GoogleTakeoutHistory`.
-    ImportGoogleLocations { data: GoogleTakeoutHistory },
+    /// If `contributor` is set, the data is recorded under that (opaque) identifier, so it can be
+    /// removed later using `DeleteContributor`
+    /// If `stay_points` is set, consecutive data points at the same place are stored as a single
+    /// stay, instead of as separate data points
+    ImportGoogleLocations {
+        data: GoogleTakeoutHistory,
+        contributor: Option<String>,
+        stay_points: Option<StayPoints>,
+    },
+    /// Adds new data to the contract, in the format of Google's Semantic Location History
+    /// export, as specified by `GoogleSemanticHistory`. `contributor` works the same as in
+    /// `ImportGoogleLocations`
+    ImportSemanticLocations {
+        data: GoogleSemanticHistory,
+        contributor: Option<String>,
+    },
+    /// Adds new data to the contract from a GPX document. Every track point must have a
+    /// timestamp. `contributor` works the same as in `ImportGoogleLocations`
+    ImportGpx {
+        data: String,
+        contributor: Option<String>,
+    },
+    /// Adds new data to the contract from a GeoJSON `FeatureCollection`. `Point` features must
+    /// have a `time` property, and `LineString` features must have a `coordTimes` property with
+    /// the time of every vertex. Times are RFC 3339 strings. `contributor` works the same as in
+    /// `ImportGoogleLocations`
+    ImportGeoJson {
+        data: String,
+        contributor: Option<String>,
+    },
+    /// Adds new data to the contract, in a compact format where the geohashes were already
+    /// computed, and data points are grouped by geohash. See `CompactCell`. `contributor` works
+    /// the same as in `ImportGoogleLocations`
+    ImportCompact {
+        cells: Vec<CompactCell>,
+        contributor: Option<String>,
+    },
+    /// Adds new data to the contract from a binary payload, which holds the same data as
+    /// `ImportCompact`, varint-encoded and optionally compressed. The format is described in
+    /// docs/Contract.md. `contributor` works the same as in `ImportGoogleLocations`
+    ImportBinary {
+        data: Binary,
+        contributor: Option<String>,
+    },
+    /// Starts an import session, which allows a large import to be split over multiple
+    /// transactions. The id of the new session is returned in the response data. Sessions hold up
+    /// to 100 chunks and 50000 data points, and expire if they are not committed within a day
+    BeginImport { contributor: Option<String> },
+    /// Stages a chunk of data in an import session. Staged data is not used until the session is
+    /// committed
+    ImportChunk {
+        session_id: u64,
+        data: GoogleTakeoutHistory,
+    },
+    /// Adds all the data staged in an import session to the contract, and closes the session
+    CommitImport { session_id: u64 },
+    /// Discards all the data staged in an import session, and closes the session
+    AbortImport { session_id: u64 },
+    /// Sets (or clears) the largest accuracy radius, in meters, of data points that are used. Data
+    /// which was already imported is not affected
+    SetMaxAccuracy { max_accuracy: Option<u32> },
+    /// Sets the default time window of the match queries, or resets it to 5 minutes before and
+    /// after if it is not set
+    SetMatchWindow { match_window: Option<MatchWindow> },
+    /// Sets the largest radius and time window that a single query can ask for, or resets them to
+    /// the defaults if not set
+    SetMatchLimits { match_limits: Option<MatchLimits> },
+    /// Sets (or clears) the distance, in meters, within which stored data points match a query.
+    /// While it is set, new data points are stored with their coordinates. Data which was already
+    /// imported without them is matched by geohash only
+    SetMatchDistance { match_distance: Option<u32> },
+    /// Sets (or clears) the weights used by the `RiskScore` query
+    SetRiskConfig { risk_config: Option<RiskConfig> },
+    /// Sets the infection status of a contributor, for all the data that was imported for them, and
+    /// for data imported for them later. Contributors are confirmed cases by default
+    SetContributorStatus {
+        contributor: String,
+        status: InfectionStatus,
+    },
+    /// Removes all the data that was imported for a contributor
+    DeleteContributor { contributor: String },
     /// ChangeDay is used to signal the contract that a day has passed, and all the oldest data,
-    /// which pertains to 14 days ago is now invalid, and should be removed. This function may take
-    /// a while, depending on how much data is stored in the contract
-    ChangeDay {},
+    /// which is older than the retention period (14 days by default) is now invalid, and should
+    /// be removed. This function may take a while, depending on how much data is stored in the
+    /// contract.
+    /// If `time` is set, buckets are rotated until the newest bucket covers `time`, which allows
+    /// catching up on multiple days in a single call
+    ChangeDay { time: Option<u64> },
+    /// Buckets are rotated automatically whenever the block time passes the newest bucket, which
+    /// is checked on every handle. Tick can be sent by anyone, and only triggers this check
+    Tick {},
+    /// Clears data of expired buckets, up to `limit` geohash cells (10000 by default). Expired data
+    /// is cleared in chunks, so that large buckets don't have to be cleared in a single
+    /// transaction. Also removes import sessions which expired. Can be sent by anyone
+    ProcessExpiry { limit: Option<u32> },
+    /// Changes the amount of days for which data is kept. Reducing the retention period deletes
+    /// the oldest data immediately
+    SetRetention { days: u32 },
     /// Admins have permissions to import data and invalidate old data
     /// This function adds a new admin which can manage the contract
     AddAdmin { address: HumanAddr },
@@ -35,7 +151,62 @@ pub enum QueryMsg {
     /// This query returns all the data points from the input which overlap with data stored
     /// in the contract. Aka, all the points that overlap in both location and time, to the accuracy
     /// defined by the contract (10 meter/5 minutes by default)
-    MatchDataPoints { data_points: Vec<GoogleLocation> },
+    /// Data points can also be given as a GPX document, in `gpx`, or as a GeoJSON
+    /// `FeatureCollection`, in `geojson`
+    /// `window` overrides the time window set for the contract, for this query only
+    /// `radius` (in meters) matches data in a larger area than the default (~7 meters), by
+    /// comparing shorter geohashes. Both are limited by the contract's `MatchLimits`
+    MatchDataPoints {
+        #[serde(default)]
+        data_points: Vec<GoogleLocation>,
+        gpx: Option<String>,
+        geojson: Option<String>,
+        window: Option<MatchWindow>,
+        radius: Option<u32>,
+    },
+    /// Same as `MatchDataPoints`, but for every overlapping input point it also returns all the
+    /// stored timestamps it overlaps with, rather than just the fact that there was an overlap
+    MatchTimes {
+        #[serde(default)]
+        data_points: Vec<GoogleLocation>,
+        gpx: Option<String>,
+        geojson: Option<String>,
+        window: Option<MatchWindow>,
+        radius: Option<u32>,
+    },
+    /// Returns all the intervals from the input which overlap with data stored in the contract.
+    /// An interval matches if it intersects a stored stay, or contains a stored data point. The
+    /// area around an interval is searched the same way as around a data point of
+    /// `MatchDataPoints`, with the same `window` and `radius`
+    MatchIntervals {
+        intervals: Vec<LocationInterval>,
+        window: Option<MatchWindow>,
+        radius: Option<u32>,
+    },
+    /// Summarizes how long the data points overlapped with data stored in the contract, per day:
+    /// the estimated minutes of overlap, the amount of separate overlap episodes, and the longest
+    /// one. Takes the same parameters as `MatchDataPoints`
+    ExposureSummary {
+        #[serde(default)]
+        data_points: Vec<GoogleLocation>,
+        gpx: Option<String>,
+        geojson: Option<String>,
+        window: Option<MatchWindow>,
+        radius: Option<u32>,
+    },
+    /// Scores the risk of the overlaps of the data points with data stored in the contract,
+    /// according to the contract's `RiskConfig`. Returns the total score, and the score of every
+    /// exposure episode (like in `ExposureSummary`)
+    RiskScore {
+        #[serde(default)]
+        data_points: Vec<GoogleLocation>,
+        gpx: Option<String>,
+        geojson: Option<String>,
+        window: Option<MatchWindow>,
+        /// the current time, in milliseconds, which days since exposure are counted from.
+        /// Defaults to the end of the newest bucket
+        time: Option<u64>,
+    },
     /// This query returns the 10 most active zone, accurate to about a ~70m radius
     HotSpot {
         /// unused
@@ -45,15 +216,53 @@ pub enum QueryMsg {
     },
     /// Returns the earliest and latest times allowed by the contract for data storage
     TimeRange {},
+    /// Returns the progress of clearing expired data
+    ExpiryStatus {},
+}
+
+/// Data returned by handles that have a response. All responses are returned as snake_case JSON
+/// objects
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleAnswer {
+    BeginImport { session_id: u64 },
 }
 
 /// General structure for query responses. All responses are returned as snake_case JSON objects
 #[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryAnswer {
-    Overlap { data_points: Vec<GeoLocationTime> },
-    HotSpotResponse { hot_spots: Vec<HotSpot> },
-    DateRange { from: u64, to: u64 },
+    Overlap {
+        data_points: Vec<GeoLocationTime>,
+    },
+    OverlapTimes {
+        matches: Vec<OverlapTimes>,
+    },
+    IntervalOverlap {
+        intervals: Vec<GeoLocationStay>,
+    },
+    ExposureSummary {
+        days: Vec<DailyExposure>,
+    },
+    RiskScore {
+        score: u64,
+        exposures: Vec<ExposureRisk>,
+    },
+    HotSpotResponse {
+        hot_spots: Vec<HotSpot>,
+    },
+    DateRange {
+        from: u64,
+        to: u64,
+    },
+    ExpiryStatus {
+        /// expired buckets which still have data in storage
+        pending_buckets: u32,
+        /// cells of the oldest pending bucket which were already cleared
+        processed_cells: u32,
+        /// cells of all the pending buckets which still have to be cleared
+        remaining_cells: u64,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, JsonSchema)]
@@ -62,6 +271,204 @@ pub struct HotSpot {
     pub power: u32,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OverlapTimes {
+    pub data_point: GeoLocationTime,
+    /// the stored timestamps (in the geohash or its neighbors) that overlap with `data_point`
+    pub times: Vec<u64>,
+    /// the stored stays (in the geohash or its neighbors) that overlap with `data_point`
+    pub stays: Vec<Stay>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DailyExposure {
+    /// start of the (UTC) day, in milliseconds since the epoch
+    pub day: u64,
+    /// estimated total minutes of overlap
+    pub minutes: u64,
+    /// the amount of separate overlap episodes
+    pub episodes: u32,
+    /// the length of the longest episode, in minutes
+    pub longest_episode: u64,
+}
+
+/// Weights used to score the risk of exposures, similar to exposure notification risk scoring.
+/// Every exposure episode is scored as the product of the weights of its proximity, duration,
+/// days since the exposure, and the infection status of the source. The total score is the sum
+/// of the scores of all the episodes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RiskConfig {
+    /// proximity tiers, from the closest to the furthest. An episode gets the weight of the
+    /// closest tier any of its data points matched in
+    pub proximity: Vec<ProximityTier>,
+    /// an episode gets the weight of the longest duration it reached. Shorter episodes get a
+    /// weight of 0
+    pub duration: Vec<DurationWeight>,
+    /// weights by the amount of days since the exposure, starting from today. Older exposures get
+    /// the last weight
+    pub days_since_exposure: Vec<u32>,
+    pub confirmed: u32,
+    pub suspected: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct ProximityTier {
+    /// in meters
+    pub radius: u32,
+    pub weight: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct DurationWeight {
+    pub min_minutes: u64,
+    pub weight: u32,
+}
+
+/// The score of a single exposure episode, and the weights it was calculated from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExposureRisk {
+    pub start: u64,
+    pub end: u64,
+    pub minutes: u64,
+    pub days_since_exposure: u64,
+    pub status: InfectionStatus,
+    pub proximity_weight: u32,
+    pub duration_weight: u32,
+    pub days_weight: u32,
+    pub status_weight: u32,
+    pub score: u64,
+}
+
+/// The infection status of the person whose data was imported. Ordered by severity
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum InfectionStatus {
+    Suspected,
+    Confirmed,
+}
+
+impl Default for InfectionStatus {
+    fn default() -> Self {
+        InfectionStatus::Confirmed
+    }
+}
+
+/// Thresholds for detecting stays in imported data. A stay is a sequence of consecutive data points
+/// which are all within `max_distance` of the first one, and which lasts at least `min_duration`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StayPoints {
+    /// in meters
+    pub max_distance: u32,
+    /// in milliseconds
+    pub min_duration: u64,
+    /// data points which are further apart than this (in milliseconds) are never part of the same
+    /// stay, since we don't know where the person was in between. Defaults to 30 minutes
+    pub max_gap: Option<u64>,
+}
+
+/// A period of time (inclusive) that someone spent at a single location
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LocationInterval {
+    pub latitudeE7: i64,
+    pub longitudeE7: i64,
+    /// in milliseconds since the epoch
+    pub start: u64,
+    /// in milliseconds since the epoch
+    pub end: u64,
+}
+
+impl TryInto<GeoLocationStay> for LocationInterval {
+    type Error = StdError;
+
+    fn try_into(self) -> StdResult<GeoLocationStay> {
+        if self.start > self.end {
+            return Err(StdError::generic_err(format!(
+                "Interval ends before it starts ({} > {})",
+                self.start, self.end
+            )));
+        }
+
+        Ok(GeoLocationStay {
+            geohash: ghash_e7(self.latitudeE7, self.longitudeE7)?,
+            start: self.start,
+            end: self.end,
+        })
+    }
+}
+
+/// How close in time a query has to be to stored data to match it, in milliseconds. A query at
+/// time `t` matches data that was stored from `t - after` up to `t + before` - that is, up to
+/// `before` milliseconds before someone was at a place, or up to `after` milliseconds after they
+/// left. Both are limited to a day
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct MatchWindow {
+    pub before: u64,
+    pub after: u64,
+}
+
+impl Default for MatchWindow {
+    fn default() -> Self {
+        Self {
+            before: OVERLAP_TIME,
+            after: OVERLAP_TIME,
+        }
+    }
+}
+
+impl MatchWindow {
+    pub fn validate(&self) -> StdResult<()> {
+        if self.before > MAX_MATCH_WINDOW || self.after > MAX_MATCH_WINDOW {
+            return Err(StdError::generic_err(format!(
+                "Match window can not be longer than {} milliseconds",
+                MAX_MATCH_WINDOW
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// The times of stored data that match a query over [start, end]
+    pub fn bounds(&self, start: u64, end: u64) -> (u64, u64) {
+        (
+            start.saturating_sub(self.after),
+            end.saturating_add(self.before),
+        )
+    }
+}
+
+/// The largest radius (in meters) and time window (in milliseconds, on each side) that a single
+/// match query can ask for. This does not limit the contract's own `MatchWindow`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct MatchLimits {
+    pub max_radius: u32,
+    pub max_window: u64,
+}
+
+impl Default for MatchLimits {
+    fn default() -> Self {
+        Self {
+            max_radius: DEFAULT_MAX_RADIUS,
+            max_window: MAX_MATCH_WINDOW,
+        }
+    }
+}
+
+impl MatchLimits {
+    pub fn validate(&self) -> StdResult<()> {
+        if self.max_window > MAX_MATCH_WINDOW {
+            return Err(StdError::generic_err(format!(
+                "Match window can not be longer than {} milliseconds",
+                MAX_MATCH_WINDOW
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GoogleTakeoutHistory {
     pub locations: Vec<GoogleLocation>,
@@ -70,30 +477,173 @@ pub struct GoogleTakeoutHistory {
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GoogleLocation {
-    pub timestampMs: Uint128,
-    pub latitudeE7: u64,
-    pub longitudeE7: u64,
+    /// time of the data point, in milliseconds since the epoch. Used by older exports
+    pub timestampMs: Option<Uint128>,
+    /// time of the data point, as an RFC 3339 string. Used by newer exports instead of
+    /// `timestampMs`
+    pub timestamp: Option<String>,
+    /// latitude in degrees, multiplied by 10^7. Negative values are south of the equator
+    pub latitudeE7: i64,
+    /// longitude in degrees, multiplied by 10^7. Negative values are west of Greenwich
+    pub longitudeE7: i64,
+    /// accuracy radius of the data point, in meters
+    pub accuracy: Option<u32>,
+    /// unused
+    pub source: Option<String>,
+    /// unused
+    pub deviceTag: Option<i64>,
 }
 
 impl TryInto<GeoLocationTime> for GoogleLocation {
     type Error = StdError;
 
     fn try_into(self) -> StdResult<GeoLocationTime> {
-        let geohash = self.hash().map_err(|_| {
-            StdError::generic_err(format!(
-                "failed to create geohash for ({}, {})",
-                self.longitudeE7, self.latitudeE7
-            ))
-        })?;
-        Ok(GeoLocationTime {
-            geohash,
-            timestamp_ms: self.timestampMs.u128() as u64,
-        })
+        GeoLocationTime::from_e7(self.latitudeE7, self.longitudeE7, self.timestamp_ms()?)
     }
 }
 
 impl GoogleLocation {
     pub fn hash(&self) -> StdResult<String> {
-        ghash(self.longitudeE7 as f64 / 1e7, self.latitudeE7 as f64 / 1e7)
+        ghash_e7(self.latitudeE7, self.longitudeE7)
+    }
+
+    /// Whether the data point is accurate enough to be used. Points without an accuracy are
+    /// always used
+    pub fn is_accurate(&self, max_accuracy: Option<u32>) -> bool {
+        match (self.accuracy, max_accuracy) {
+            (Some(accuracy), Some(max)) => accuracy <= max,
+            _ => true,
+        }
+    }
+
+    /// Time of the data point in milliseconds, from whichever timestamp field the export uses
+    pub fn timestamp_ms(&self) -> StdResult<u64> {
+        match (&self.timestampMs, &self.timestamp) {
+            (Some(ms), _) => Ok(ms.u128() as u64),
+            (None, Some(timestamp)) => parse_rfc3339(timestamp),
+            (None, None) => Err(StdError::generic_err(
+                "Data point must have either a timestampMs or a timestamp",
+            )),
+        }
+    }
+}
+
+/// Google's Semantic Location History export. Only the fields we use are listed here - the
+/// timeline is a list of objects which each contain either a `placeVisit` or an `activitySegment`
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GoogleSemanticHistory {
+    pub timelineObjects: Vec<TimelineObject>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TimelineObject {
+    pub placeVisit: Option<PlaceVisit>,
+    pub activitySegment: Option<ActivitySegment>,
+}
+
+/// A stay at a single place, for the whole duration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PlaceVisit {
+    /// the centroid of the place
+    pub location: SemanticLocation,
+    pub duration: SemanticDuration,
+}
+
+/// Movement between two places
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ActivitySegment {
+    pub startLocation: Option<SemanticLocation>,
+    pub endLocation: Option<SemanticLocation>,
+    pub duration: SemanticDuration,
+    /// points along the route, without timestamps
+    pub waypointPath: Option<WaypointPath>,
+    /// raw location samples along the route, with timestamps
+    pub simplifiedRawPath: Option<RawPath>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SemanticLocation {
+    pub latitudeE7: i64,
+    pub longitudeE7: i64,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SemanticDuration {
+    pub startTimestampMs: Uint128,
+    pub endTimestampMs: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WaypointPath {
+    pub waypoints: Vec<Waypoint>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Waypoint {
+    pub latE7: i64,
+    pub lngE7: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RawPath {
+    pub points: Vec<RawPathPoint>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RawPathPoint {
+    pub latE7: i64,
+    pub lngE7: i64,
+    pub timestampMs: Uint128,
+}
+
+/// All the data points of a single geohash. The geohash is given either as a string (`geohash`),
+/// or in its integer form (`geohash_int`), and must have the same precision as the contract uses
+/// (9 characters).
+///
+/// To save space, `times` are delta-encoded: the first value is a timestamp in milliseconds, and
+/// every value after that is the difference from the previous timestamp
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CompactCell {
+    pub geohash: Option<String>,
+    pub geohash_int: Option<u64>,
+    pub times: Vec<u64>,
+}
+
+impl CompactCell {
+    pub fn data_points(self) -> StdResult<Vec<GeoLocationTime>> {
+        let geohash = match (self.geohash, self.geohash_int) {
+            (Some(geohash), None) => {
+                validate_geohash(&geohash)?;
+                geohash
+            }
+            (None, Some(value)) => geohash_from_int(value)?,
+            _ => {
+                return Err(StdError::generic_err(
+                    "Compact cell must have exactly one of geohash or geohash_int",
+                ))
+            }
+        };
+
+        let mut data_points = Vec::with_capacity(self.times.len());
+        let mut time: u64 = 0;
+        for delta in self.times {
+            time = time
+                .checked_add(delta)
+                .ok_or_else(|| StdError::generic_err("Compact cell timestamp overflow"))?;
+            data_points.push(GeoLocationTime {
+                geohash: geohash.clone(),
+                timestamp_ms: time,
+                position: None,
+            });
+        }
+
+        Ok(data_points)
     }
 }
@@ -1,25 +1,47 @@
 use core::option::Option::{None, Some};
 use core::result::Result::Ok;
+use std::cmp::Ordering;
 
 use bincode2;
 use cosmwasm_std::{ReadonlyStorage, StdError, StdResult, Storage};
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
 use serde::{Deserialize, Serialize};
 
-use crate::bucket::BucketName;
+use crate::bucket::BucketId;
 
-pub const ONE_DAY: u64 = 1000 * 60 * 60 * 24;
+pub const ONE_HOUR: u64 = 1000 * 60 * 60;
+pub const ONE_DAY: u64 = ONE_HOUR * 24;
+pub const DEFAULT_RETENTION_DAYS: u32 = 14;
+pub const MAX_RETENTION_DAYS: u32 = 90;
 pub static POINTERS_KEY: &[u8] = b"pointers";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Pointer {
     pub start_time: u64,
     pub end_time: u64,
-    pub bucket: BucketName,
+    pub bucket: BucketId,
 }
 
-/// `Pointers` is a structure that we used to identify our daily buckets,
-///
+impl Pointer {
+    /// Create a pointer to a new bucket, which covers `span` milliseconds starting at `start_time`
+    pub fn new(start_time: u64, span: u64) -> Self {
+        Self {
+            start_time,
+            end_time: start_time + span - 1,
+            bucket: BucketId(start_time),
+        }
+    }
+}
+
+/// The amount of buckets needed to cover the retention period, when each bucket spans
+/// `bucket_span` milliseconds
+pub fn bucket_count(retention_days: u32, bucket_span: u64) -> u32 {
+    (retention_days as u64 * ONE_DAY / bucket_span) as u32
+}
+
+/// `Pointers` is a structure that we used to identify our buckets. It holds a ring of consecutive
+/// buckets which cover the retention window, sorted from oldest to newest. Each bucket covers a
+/// day by default, but can be configured to cover a shorter period
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Pointers(pub Vec<Pointer>);
 
@@ -45,13 +67,27 @@ impl Pointers {
         Ok(Self::default())
     }
 
-    pub fn find_bucket(&self, time: u64) -> Option<BucketName> {
-        for p in &self.0 {
-            if time >= p.start_time && time <= p.end_time {
-                return Some(p.bucket);
-            }
-        }
-        None
+    pub fn find_bucket(&self, time: u64) -> Option<BucketId> {
+        // pointers are sorted and don't overlap, so we can binary search for the right one
+        self.0
+            .binary_search_by(|p| {
+                if time < p.start_time {
+                    Ordering::Greater
+                } else if time > p.end_time {
+                    Ordering::Less
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()
+            .map(|idx| self.0[idx].bucket)
+    }
+
+    /// All the pointers whose buckets overlap with the window [from, to]
+    pub fn overlapping(&self, from: u64, to: u64) -> impl Iterator<Item = &Pointer> {
+        self.0
+            .iter()
+            .filter(move |p| p.start_time <= to && p.end_time >= from)
     }
 
     pub fn sort(&mut self) {
@@ -63,6 +99,18 @@ impl Pointers {
         self.0.pop()
     }
 
+    /// Removes the oldest pointer from the ring
+    pub fn pop_oldest(&mut self) -> Option<Pointer> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(self.0.remove(0))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn insert(&mut self, ptr: Pointer) {
         self.0.push(ptr);
         self.sort();
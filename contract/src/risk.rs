@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{
+    to_binary, Api, Env, Extern, HandleResponse, Querier, QueryResult, StdError, StdResult, Storage,
+};
+
+use crate::bucket::{BucketId, DailyBucket};
+use crate::data::proximity;
+use crate::exposure::group_episodes;
+use crate::geohash::{GeoLocationTime, SearchArea};
+use crate::msg::{ExposureRisk, InfectionStatus, MatchWindow, QueryAnswer, RiskConfig};
+use crate::pointer::{Pointers, ONE_DAY};
+use crate::state::{config, config_read};
+
+impl RiskConfig {
+    pub fn validate(&self) -> StdResult<()> {
+        if self.proximity.is_empty() || self.days_since_exposure.is_empty() {
+            return Err(StdError::generic_err(
+                "Risk config needs at least one proximity tier and one days since exposure weight",
+            ));
+        }
+        if self
+            .proximity
+            .windows(2)
+            .any(|tiers| tiers[0].radius >= tiers[1].radius)
+        {
+            return Err(StdError::generic_err(
+                "Proximity tiers must be sorted from the smallest radius to the largest",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn duration_weight(&self, minutes: u64) -> u32 {
+        self.duration
+            .iter()
+            .filter(|d| d.min_minutes <= minutes)
+            .max_by_key(|d| d.min_minutes)
+            .map(|d| d.weight)
+            .unwrap_or_default()
+    }
+
+    fn days_weight(&self, days: u64) -> u32 {
+        let last = self.days_since_exposure.len() - 1;
+        self.days_since_exposure[(days as usize).min(last)]
+    }
+
+    fn status_weight(&self, status: InfectionStatus) -> u32 {
+        match status {
+            InfectionStatus::Confirmed => self.confirmed,
+            InfectionStatus::Suspected => self.suspected,
+        }
+    }
+}
+
+pub fn set_risk_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    risk_config: Option<RiskConfig>,
+) -> StdResult<HandleResponse> {
+    if let Some(risk_config) = &risk_config {
+        risk_config.validate()?;
+    }
+
+    let mut state = config(&mut deps.storage).load()?;
+
+    state.risk_config = risk_config;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse::default())
+}
+
+/// The closest proximity tier (by index) that a data point matches in, and the most severe status
+/// of the data it matched in that tier
+type TierMatch = (usize, InfectionStatus);
+
+pub fn risk_score<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    data_points: Vec<GeoLocationTime>,
+    window: MatchWindow,
+    time: Option<u64>,
+) -> QueryResult {
+    let state = config_read(&deps.storage).load()?;
+    let risk_config = state
+        .risk_config
+        .ok_or_else(|| StdError::generic_err("Risk scoring is not configured"))?;
+    let pointers = Pointers::load(&deps.storage)?;
+
+    let mut bucket_cache: HashMap<BucketId, DailyBucket> = HashMap::default();
+
+    let mut matches: Vec<(u64, Option<TierMatch>)> = Vec::with_capacity(data_points.len());
+    for geoloc in data_points {
+        let (from, to) = window.bounds(geoloc.timestamp_ms, geoloc.timestamp_ms);
+        let latitude = geoloc.latitude()?;
+
+        // tiers are sorted from the closest, so the first one we match in is the one we want
+        let mut found: Option<TierMatch> = None;
+        for (tier, proximity_tier) in risk_config.proximity.iter().enumerate() {
+            let area = SearchArea::for_radius(proximity_tier.radius, latitude);
+            let near = proximity(&geoloc, state.match_distance.map(|_| proximity_tier.radius));
+
+            let mut status: Option<InfectionStatus> = None;
+            for pointer in pointers.overlapping(from, to) {
+                let bucket = bucket_cache
+                    .entry(pointer.bucket)
+                    .or_insert_with(|| DailyBucket::new(pointer.bucket));
+
+                let matched = bucket.match_status(
+                    &deps.storage,
+                    &geoloc.geohash,
+                    &area,
+                    geoloc.timestamp_ms,
+                    &window,
+                    near,
+                )?;
+                status = status.max(matched);
+            }
+
+            if let Some(status) = status {
+                found = Some((tier, status));
+                break;
+            }
+        }
+        matches.push((geoloc.timestamp_ms, found));
+    }
+
+    // queries don't know the current time. Unless we're told otherwise, assume that the buckets
+    // are up to date, so the newest one holds today
+    let now = time
+        .or_else(|| pointers.last().map(|p| p.end_time))
+        .unwrap_or_default();
+
+    let mut score: u64 = 0;
+    let mut exposures: Vec<ExposureRisk> = vec![];
+    for (episode, tiers) in group_episodes(matches) {
+        // every episode has at least one match
+        let closest = tiers.iter().map(|(tier, _)| *tier).min().unwrap();
+        let status = tiers.iter().map(|(_, status)| *status).max().unwrap();
+
+        let minutes = episode.minutes();
+        let days_since_exposure = now.saturating_sub(episode.end) / ONE_DAY;
+
+        let proximity_weight = risk_config.proximity[closest].weight;
+        let duration_weight = risk_config.duration_weight(minutes);
+        let days_weight = risk_config.days_weight(days_since_exposure);
+        let status_weight = risk_config.status_weight(status);
+
+        let episode_score = proximity_weight as u64
+            * duration_weight as u64
+            * days_weight as u64
+            * status_weight as u64;
+        score = score.saturating_add(episode_score);
+
+        exposures.push(ExposureRisk {
+            start: episode.start,
+            end: episode.end,
+            minutes,
+            days_since_exposure,
+            status,
+            proximity_weight,
+            duration_weight,
+            days_weight,
+            status_weight,
+            score: episode_score,
+        });
+    }
+
+    to_binary(&QueryAnswer::RiskScore { score, exposures })
+}
@@ -0,0 +1,102 @@
+use std::cmp::{max, min};
+
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::geohash::{ghash_e7, GeoLocationStay, GeoLocationTime};
+use crate::msg::{ActivitySegment, GoogleSemanticHistory, PlaceVisit, SemanticDuration};
+
+/// Convert a Semantic Location History export to our internal structure (geohash + time).
+///
+/// Place visits only have a start and an end time, so they are stored as stays - that way a query
+/// at any time during the visit will match. Visits are clamped to `from` and `to`, since data
+/// outside of our window is thrown away anyway.
+pub fn semantic_data_points(
+    history: GoogleSemanticHistory,
+    from: u64,
+    to: u64,
+) -> StdResult<(Vec<GeoLocationTime>, Vec<GeoLocationStay>)> {
+    let mut data_points: Vec<GeoLocationTime> = vec![];
+    let mut stays: Vec<GeoLocationStay> = vec![];
+
+    for object in history.timelineObjects {
+        if let Some(visit) = object.placeVisit {
+            if let Some(stay) = place_visit_stay(&visit, from, to)? {
+                stays.push(stay);
+            }
+        }
+        if let Some(segment) = object.activitySegment {
+            activity_segment_points(&segment, &mut data_points)?;
+        }
+    }
+
+    Ok((data_points, stays))
+}
+
+fn duration_bounds(duration: &SemanticDuration) -> StdResult<(u64, u64)> {
+    let start = duration.startTimestampMs.u128() as u64;
+    let end = duration.endTimestampMs.u128() as u64;
+    if end < start {
+        return Err(StdError::generic_err(format!(
+            "Invalid duration: ends ({}) before it starts ({})",
+            end, start
+        )));
+    }
+
+    Ok((start, end))
+}
+
+fn place_visit_stay(visit: &PlaceVisit, from: u64, to: u64) -> StdResult<Option<GeoLocationStay>> {
+    let (start, end) = duration_bounds(&visit.duration)?;
+    let geohash = ghash_e7(visit.location.latitudeE7, visit.location.longitudeE7)?;
+
+    let first = max(start, from);
+    let last = min(end, to);
+    if first > last {
+        return Ok(None);
+    }
+
+    Ok(Some(GeoLocationStay {
+        geohash,
+        start: first,
+        end: last,
+    }))
+}
+
+fn activity_segment_points(
+    segment: &ActivitySegment,
+    data_points: &mut Vec<GeoLocationTime>,
+) -> StdResult<()> {
+    let (start, end) = duration_bounds(&segment.duration)?;
+
+    // waypoints do not have timestamps, so we assume they are spread evenly over the duration of
+    // the segment, between its start and end locations
+    let mut route: Vec<(i64, i64)> = vec![];
+    if let Some(loc) = &segment.startLocation {
+        route.push((loc.latitudeE7, loc.longitudeE7));
+    }
+    if let Some(path) = &segment.waypointPath {
+        route.extend(path.waypoints.iter().map(|w| (w.latE7, w.lngE7)));
+    }
+    if let Some(loc) = &segment.endLocation {
+        route.push((loc.latitudeE7, loc.longitudeE7));
+    }
+
+    let steps = max(route.len(), 2) as u128 - 1;
+    for (i, (lat, lng)) in route.into_iter().enumerate() {
+        let offset = (end - start) as u128 * i as u128 / steps;
+        data_points.push(GeoLocationTime::from_e7(lat, lng, start + offset as u64)?);
+    }
+
+    // raw samples already have their own timestamps
+    if let Some(path) = &segment.simplifiedRawPath {
+        for point in &path.points {
+            data_points.push(GeoLocationTime::from_e7(
+                point.latE7,
+                point.lngE7,
+                point.timestampMs.u128() as u64,
+            )?);
+        }
+    }
+
+    Ok(())
+}
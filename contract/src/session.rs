@@ -0,0 +1,326 @@
+use std::convert::TryInto;
+
+use bincode2;
+use cosmwasm_std::{
+    log, to_binary, Api, Env, Extern, HandleResponse, HumanAddr, Querier, ReadonlyStorage,
+    StdError, StdResult, Storage,
+};
+use cosmwasm_storage::{singleton, PrefixedStorage, ReadonlyPrefixedStorage};
+use serde::{Deserialize, Serialize};
+
+use crate::data::insert_data_points;
+use crate::geohash::{GeoLocationTime, Position};
+use crate::msg::{GoogleTakeoutHistory, HandleAnswer};
+use crate::pointer::ONE_DAY;
+use crate::state::config_read;
+
+pub static SESSIONS_KEY: &[u8] = b"sessions";
+pub static SESSION_CHUNKS_KEY: &[u8] = b"session_chunks";
+pub static NEXT_SESSION_KEY: &[u8] = b"next_session";
+pub static OPEN_SESSIONS_KEY: &[u8] = b"open_sessions";
+
+/// Sessions which are not committed (or aborted) within this time expire, and their staged data
+/// is removed by `process_expiry`
+pub const SESSION_TTL: u64 = ONE_DAY;
+/// The most chunks that can be staged in a single session
+pub const MAX_SESSION_CHUNKS: u32 = 100;
+/// The most data points that can be staged in a single session, so that a commit always fits in a
+/// single transaction
+pub const MAX_SESSION_POINTS: u64 = 50000;
+
+/// An `ImportSession` lets a large import be split over multiple transactions. Chunks are staged
+/// under the session, and are only inserted into the buckets and the hot spot tracker once the
+/// session is committed, so a failed upload never leaves partial data in the contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImportSession {
+    pub id: u64,
+    /// block time the session was started at, in milliseconds
+    pub started: u64,
+    /// the admin who started the session. Only they can add to it or commit it
+    pub owner: HumanAddr,
+    /// the contributor the imported data will be recorded under, if any
+    pub contributor: Option<String>,
+    /// amount of chunks staged so far
+    pub chunks: u32,
+    /// amount of data points staged so far
+    pub points: u64,
+}
+
+/// A data point as it is staged in a session. Unlike `GeoLocationTime`, this keeps the position,
+/// so data imported in a session can still be matched by distance
+#[derive(Serialize, Deserialize)]
+struct StagedPoint {
+    geohash: String,
+    timestamp_ms: u64,
+    position: Option<Position>,
+}
+
+impl From<&GeoLocationTime> for StagedPoint {
+    fn from(geoloc: &GeoLocationTime) -> Self {
+        Self {
+            geohash: geoloc.geohash.clone(),
+            timestamp_ms: geoloc.timestamp_ms,
+            position: geoloc.position,
+        }
+    }
+}
+
+impl From<StagedPoint> for GeoLocationTime {
+    fn from(staged: StagedPoint) -> Self {
+        Self {
+            geohash: staged.geohash,
+            timestamp_ms: staged.timestamp_ms,
+            position: staged.position,
+        }
+    }
+}
+
+impl ImportSession {
+    pub fn store<S: Storage>(&self, store: &mut S) -> StdResult<()> {
+        let mut config_store = PrefixedStorage::new(SESSIONS_KEY, store);
+        let as_bytes = bincode2::serialize(&self)
+            .map_err(|_| StdError::generic_err("Error serializing import session"))?;
+
+        config_store.set(&self.id.to_be_bytes(), &as_bytes);
+
+        Ok(())
+    }
+
+    pub fn load<S: ReadonlyStorage>(store: &S, id: u64) -> StdResult<Self> {
+        let config_store = ReadonlyPrefixedStorage::new(SESSIONS_KEY, store);
+        if let Some(temp) = config_store.get(&id.to_be_bytes()) {
+            let session: Self = bincode2::deserialize(&temp)
+                .map_err(|_| StdError::generic_err("Error deserializing import session"))?;
+            return Ok(session);
+        }
+
+        Err(StdError::generic_err(format!(
+            "Import session {} does not exist",
+            id
+        )))
+    }
+
+    /// Load a session, and make sure it belongs to the sender, and has not expired
+    pub fn load_owned<S: ReadonlyStorage>(store: &S, id: u64, env: &Env) -> StdResult<Self> {
+        let session = Self::load(store, id)?;
+        if session.owner != env.message.sender {
+            return Err(StdError::generic_err(format!(
+                "Import session {} was started by a different address",
+                id
+            )));
+        }
+        if session.is_expired(env.block.time * 1000) {
+            return Err(StdError::generic_err(format!(
+                "Import session {} has expired",
+                id
+            )));
+        }
+
+        Ok(session)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.started + SESSION_TTL < now
+    }
+
+    pub fn store_chunk<S: Storage>(
+        &mut self,
+        store: &mut S,
+        data_points: &[GeoLocationTime],
+    ) -> StdResult<()> {
+        let id = self.id.to_be_bytes();
+        let mut chunk_store = PrefixedStorage::multilevel(&[SESSION_CHUNKS_KEY, &id], store);
+        let staged: Vec<StagedPoint> = data_points.iter().map(StagedPoint::from).collect();
+        let as_bytes = bincode2::serialize(&staged)
+            .map_err(|_| StdError::generic_err("Error serializing import chunk"))?;
+
+        chunk_store.set(&self.chunks.to_be_bytes(), &as_bytes);
+
+        self.chunks += 1;
+        self.points += data_points.len() as u64;
+
+        Ok(())
+    }
+
+    pub fn load_chunk<S: ReadonlyStorage>(
+        &self,
+        store: &S,
+        chunk: u32,
+    ) -> StdResult<Vec<GeoLocationTime>> {
+        let id = self.id.to_be_bytes();
+        let chunk_store = ReadonlyPrefixedStorage::multilevel(&[SESSION_CHUNKS_KEY, &id], store);
+        if let Some(temp) = chunk_store.get(&chunk.to_be_bytes()) {
+            let staged: Vec<StagedPoint> = bincode2::deserialize(&temp)
+                .map_err(|_| StdError::generic_err("Error deserializing import chunk"))?;
+            return Ok(staged.into_iter().map(GeoLocationTime::from).collect());
+        }
+
+        Ok(vec![])
+    }
+
+    /// Remove the session and all of its staged chunks
+    pub fn remove<S: Storage>(&self, store: &mut S) -> StdResult<()> {
+        {
+            let id = self.id.to_be_bytes();
+            let mut chunk_store = PrefixedStorage::multilevel(&[SESSION_CHUNKS_KEY, &id], store);
+            for chunk in 0..self.chunks {
+                chunk_store.remove(&chunk.to_be_bytes());
+            }
+        }
+
+        let mut config_store = PrefixedStorage::new(SESSIONS_KEY, store);
+        config_store.remove(&self.id.to_be_bytes());
+
+        let mut open = open_sessions(store)?;
+        open.retain(|id| *id != self.id);
+        singleton(store, OPEN_SESSIONS_KEY).save(&open)
+    }
+}
+
+fn next_session_id<S: Storage>(store: &mut S) -> StdResult<u64> {
+    let mut counter = singleton(store, NEXT_SESSION_KEY);
+    let id: u64 = counter.may_load()?.unwrap_or_default();
+    counter.save(&(id + 1))?;
+
+    Ok(id)
+}
+
+/// The ids of the sessions which were not committed or aborted yet. Ids are increasing, so the
+/// sessions are sorted from the oldest
+fn open_sessions<S: Storage>(store: &mut S) -> StdResult<Vec<u64>> {
+    Ok(singleton(store, OPEN_SESSIONS_KEY)
+        .may_load()?
+        .unwrap_or_default())
+}
+
+/// Remove all the sessions which expired before `now`, with their staged data. Returns the amount
+/// of sessions that were removed
+pub fn expire_sessions<S: Storage>(store: &mut S, now: u64) -> StdResult<u32> {
+    let mut expired: u32 = 0;
+    for id in open_sessions(store)? {
+        let session = ImportSession::load(store, id)?;
+        if !session.is_expired(now) {
+            break;
+        }
+        session.remove(store)?;
+        expired += 1;
+    }
+
+    Ok(expired)
+}
+
+pub fn begin_import<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    contributor: Option<String>,
+) -> StdResult<HandleResponse> {
+    let session = ImportSession {
+        id: next_session_id(&mut deps.storage)?,
+        started: env.block.time * 1000,
+        owner: env.message.sender,
+        contributor,
+        chunks: 0,
+        points: 0,
+    };
+    session.store(&mut deps.storage)?;
+
+    let mut open = open_sessions(&mut deps.storage)?;
+    open.push(session.id);
+    singleton(&mut deps.storage, OPEN_SESSIONS_KEY).save(&open)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("session_id", session.id)],
+        data: Some(to_binary(&HandleAnswer::BeginImport {
+            session_id: session.id,
+        })?),
+    })
+}
+
+pub fn import_chunk<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    session_id: u64,
+    data: GoogleTakeoutHistory,
+) -> StdResult<HandleResponse> {
+    let mut session = ImportSession::load_owned(&deps.storage, session_id, &env)?;
+
+    let max_accuracy = config_read(&deps.storage).load()?.max_accuracy;
+
+    // convert (and validate) the data now, so that bad data is rejected before the commit
+    let mut data_points: Vec<GeoLocationTime> = Vec::with_capacity(data.locations.len());
+    for dp in data.locations {
+        if dp.is_accurate(max_accuracy) {
+            data_points.push(dp.try_into()?);
+        }
+    }
+
+    if session.chunks >= MAX_SESSION_CHUNKS {
+        return Err(StdError::generic_err(format!(
+            "Import session can not have more than {} chunks",
+            MAX_SESSION_CHUNKS
+        )));
+    }
+    if session.points + data_points.len() as u64 > MAX_SESSION_POINTS {
+        return Err(StdError::generic_err(format!(
+            "Import session can not have more than {} data points",
+            MAX_SESSION_POINTS
+        )));
+    }
+
+    session.store_chunk(&mut deps.storage, &data_points)?;
+    session.store(&mut deps.storage)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("session_id", session.id),
+            log("chunks", session.chunks),
+            log("points", session.points),
+        ],
+        data: None,
+    })
+}
+
+/// Insert all the data staged in the session, and remove the session
+pub fn commit_import<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    session_id: u64,
+) -> StdResult<HandleResponse> {
+    let session = ImportSession::load_owned(&deps.storage, session_id, &env)?;
+
+    let mut data_points: Vec<GeoLocationTime> = Vec::with_capacity(session.points as usize);
+    for chunk in 0..session.chunks {
+        data_points.extend(session.load_chunk(&deps.storage, chunk)?);
+    }
+
+    let imported = insert_data_points(
+        &mut deps.storage,
+        data_points,
+        session.contributor.as_deref(),
+    )?;
+    session.remove(&mut deps.storage)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("session_id", session.id), log("imported", imported)],
+        data: None,
+    })
+}
+
+/// Discard all the data staged in the session, and remove the session
+pub fn abort_import<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    session_id: u64,
+) -> StdResult<HandleResponse> {
+    let session = ImportSession::load_owned(&deps.storage, session_id, &env)?;
+    session.remove(&mut deps.storage)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("session_id", session.id)],
+        data: None,
+    })
+}
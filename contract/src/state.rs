@@ -4,11 +4,26 @@ use serde::{Deserialize, Serialize};
 use cosmwasm_std::{HumanAddr, Storage};
 use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
 
+use crate::msg::{MatchLimits, MatchWindow, RiskConfig};
+
 pub static CONFIG_KEY: &[u8] = b"config";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub admin: Vec<HumanAddr>,
+    /// the period covered by each bucket, in milliseconds
+    pub bucket_span: u64,
+    /// data points with a larger accuracy radius (in meters) than this are ignored
+    pub max_accuracy: Option<u32>,
+    /// the default time window of the match queries. 5 minutes before and after if not set
+    pub match_window: Option<MatchWindow>,
+    /// the largest radius and window a query can ask for. `MatchLimits::default()` if not set
+    pub match_limits: Option<MatchLimits>,
+    /// if set, data points are stored with their position, and only match queries within this
+    /// many meters of them
+    pub match_distance: Option<u32>,
+    /// the weights used by the risk score query. The query is disabled if not set
+    pub risk_config: Option<RiskConfig>,
 }
 
 pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
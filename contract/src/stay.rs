@@ -0,0 +1,90 @@
+use std::convert::TryInto;
+
+use cosmwasm_std::StdResult;
+
+use crate::geohash::{distance_e7, GeoLocationStay, GeoLocationTime};
+use crate::msg::{GoogleLocation, StayPoints};
+
+/// Default for `StayPoints::max_gap`
+pub const DEFAULT_MAX_GAP: u64 = 1000 * 60 * 30;
+
+/// Collapse consecutive data points at the same place into stays.
+///
+/// Data points are sorted by time, and every data point starts a candidate stay, which continues
+/// as long as the following points are within `max_distance` of it, and no more than `max_gap`
+/// apart. If the candidate lasts at least `min_duration` it is stored as a single stay in the
+/// cell of its first point. Otherwise, its first point is kept as a regular data point, and we try
+/// again from the next one
+pub fn detect_stays(
+    data_points: Vec<GoogleLocation>,
+    config: &StayPoints,
+) -> StdResult<(Vec<GeoLocationTime>, Vec<GeoLocationStay>)> {
+    let max_gap = config.max_gap.unwrap_or(DEFAULT_MAX_GAP);
+
+    let mut timed: Vec<(u64, GoogleLocation)> = Vec::with_capacity(data_points.len());
+    for dp in data_points {
+        timed.push((dp.timestamp_ms()?, dp));
+    }
+    timed.sort_by_key(|(time, _)| *time);
+
+    let mut points: Vec<GeoLocationTime> = vec![];
+    let mut stays: Vec<GeoLocationStay> = vec![];
+
+    let mut i = 0;
+    while i < timed.len() {
+        let (start, anchor) = &timed[i];
+
+        // find the last point of the stay that starts at `anchor`
+        let mut last = i;
+        while last + 1 < timed.len() {
+            let (prev_time, _) = &timed[last];
+            let (time, next) = &timed[last + 1];
+            let distance = distance_e7(
+                anchor.latitudeE7,
+                anchor.longitudeE7,
+                next.latitudeE7,
+                next.longitudeE7,
+            );
+            if time - prev_time > max_gap || distance > config.max_distance as f64 {
+                break;
+            }
+            last += 1;
+        }
+
+        let end = timed[last].0;
+        if last > i && end - start >= config.min_duration {
+            stays.push(GeoLocationStay {
+                geohash: anchor.hash()?,
+                start: *start,
+                end,
+            });
+            i = last + 1;
+        } else {
+            points.push(anchor.clone().try_into()?);
+            i += 1;
+        }
+    }
+
+    Ok((points, stays))
+}
+
+/// Merge stays in the same cell which overlap (or touch) within a single import. Stored cells merge
+/// the stays of different imports as well, but the contributor record keeps the stays as they
+/// were imported, so merging them here keeps that record (and the hot spot counts) small
+pub fn merge_stays(mut stays: Vec<GeoLocationStay>) -> Vec<GeoLocationStay> {
+    stays.sort_by(|a, b| (&a.geohash, a.start).cmp(&(&b.geohash, b.start)));
+
+    let mut merged: Vec<GeoLocationStay> = Vec::with_capacity(stays.len());
+    for stay in stays {
+        match merged.last_mut() {
+            Some(last)
+                if last.geohash == stay.geohash && stay.start <= last.end.saturating_add(1) =>
+            {
+                last.end = last.end.max(stay.end);
+            }
+            _ => merged.push(stay),
+        }
+    }
+
+    merged
+}
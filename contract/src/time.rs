@@ -1,47 +1,253 @@
-use crate::bucket::DailyBucket;
-use crate::hotspotmap::HotspotMap;
+use crate::expiry::{expire_cells, ExpiryQueue, DEFAULT_EXPIRY_LIMIT};
 use crate::msg::QueryAnswer;
-use crate::pointer::{Pointer, Pointers, ONE_DAY};
+use crate::pointer::{bucket_count, Pointer, Pointers, MAX_RETENTION_DAYS, ONE_DAY};
+use crate::state::config_read;
 use cosmwasm_std::{
-    to_binary, Api, Env, Extern, HandleResponse, Querier, QueryResult, StdResult, Storage,
+    log, to_binary, Api, Env, Extern, HandleResponse, Querier, QueryResult, StdError, StdResult,
+    Storage,
 };
 
+pub fn validate_retention(days: u32) -> StdResult<()> {
+    if days == 0 || days > MAX_RETENTION_DAYS {
+        return Err(StdError::generic_err(format!(
+            "Retention period must be between 1 and {} days",
+            MAX_RETENTION_DAYS
+        )));
+    }
+    Ok(())
+}
+
+/// Buckets must cover a whole number of hours, and a whole number of buckets must fit in a day, so
+/// that bucket boundaries line up with the retention period
+pub fn validate_bucket_hours(hours: u32) -> StdResult<()> {
+    if hours == 0 || 24 % hours != 0 {
+        return Err(StdError::generic_err(
+            "Bucket span must be a whole divisor of 24 hours (1, 2, 3, 4, 6, 8, 12 or 24)",
+        ));
+    }
+    Ok(())
+}
+
+/// Parse an RFC 3339 timestamp (e.g. `2020-09-21T13:12:31.455Z`) to milliseconds since the
+/// epoch. Fractions of a millisecond are truncated
+pub fn parse_rfc3339(timestamp: &str) -> StdResult<u64> {
+    let invalid = || StdError::generic_err(format!("Invalid RFC 3339 timestamp: {}", timestamp));
+
+    // everything below slices the string by byte offsets, which only works for ASCII
+    let bytes = timestamp.as_bytes();
+    if !timestamp.is_ascii()
+        || bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !(bytes[10] == b'T' || bytes[10] == b't' || bytes[10] == b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return Err(invalid());
+    }
+
+    let number = |from: usize, to: usize| -> StdResult<u64> {
+        let digits = &timestamp[from..to];
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        digits.parse::<u64>().map_err(|_| invalid())
+    };
+
+    let (year, month, day) = (number(0, 4)?, number(5, 7)?, number(8, 10)?);
+    let (hour, minute, second) = (number(11, 13)?, number(14, 16)?, number(17, 19)?);
+    if year < 1970 || month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    // allow leap seconds
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+
+    // optional fraction of a second
+    let mut pos = 19;
+    let mut millis: u64 = 0;
+    if bytes[pos] == b'.' {
+        let start = pos + 1;
+        pos = start;
+        while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+            pos += 1;
+        }
+        if pos == start {
+            return Err(invalid());
+        }
+        let mut fraction = timestamp[start..std::cmp::min(pos, start + 3)].to_string();
+        while fraction.len() < 3 {
+            fraction.push('0');
+        }
+        millis = fraction.parse::<u64>().map_err(|_| invalid())?;
+    }
+
+    // time zone offset, in seconds east of UTC
+    let offset: i64 = match &timestamp[pos..] {
+        "Z" | "z" => 0,
+        tz if tz.len() == 6 && (tz.starts_with('+') || tz.starts_with('-')) && &tz[3..4] == ":" => {
+            let hours = number(pos + 1, pos + 3)? as i64;
+            let minutes = number(pos + 4, pos + 6)? as i64;
+            if hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            let sign = if tz.starts_with('-') { -1 } else { 1 };
+            sign * (hours * 3600 + minutes * 60)
+        }
+        _ => return Err(invalid()),
+    };
+
+    let days = days_from_civil(year, month, day);
+    let seconds = (days * 86400 + hour * 3600 + minute * 60 + second) as i64 - offset;
+    if seconds < 0 {
+        return Err(invalid());
+    }
+
+    Ok(seconds as u64 * 1000 + millis)
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a date in the proleptic Gregorian calendar. `year` must be 1970 or
+/// later
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
 pub fn query_dates<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> QueryResult {
     let pointers = Pointers::load(&deps.storage)?;
 
-    let to = pointers.first().unwrap().end_time;
-    let from = pointers.last().unwrap().start_time;
+    let from = pointers.first().unwrap().start_time;
+    let to = pointers.last().unwrap().end_time;
 
     return to_binary(&QueryAnswer::DateRange { from, to });
 }
 
+/// Signal that a day has passed. If `time` is set, the buckets are rotated until the newest
+/// bucket covers `time`, which lets us catch up if a few days were missed
 pub fn new_day<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     _env: Env,
+    time: Option<u64>,
 ) -> StdResult<HandleResponse> {
-    let mut pointers = Pointers::load(&deps.storage)?;
+    let target = match time {
+        Some(time) => time,
+        None => Pointers::load(&deps.storage)?.last().unwrap().end_time + ONE_DAY,
+    };
 
-    let old_day = pointers.pop().unwrap();
-    let old_bucket = DailyBucket::load(&deps.storage, &old_day.bucket)?;
+    let rotated = rotate_buckets(&mut deps.storage, target)?;
 
-    let new_day = Pointer {
-        start_time: pointers.first().unwrap().end_time,
-        end_time: pointers.first().unwrap().end_time + ONE_DAY,
-        bucket: old_day.bucket,
-    };
+    // clear as much of the expired data as we can right away, the rest can be cleared with
+    // `ProcessExpiry`
+    let expired = expire_cells(&mut deps.storage, DEFAULT_EXPIRY_LIMIT)?;
 
-    let bucket = DailyBucket::default();
-    bucket.store(&mut deps.storage, &old_day.bucket)?;
-    pointers.insert(new_day);
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("rotated_buckets", rotated),
+            log("expired_cells", expired),
+        ],
+        data: None,
+    })
+}
 
-    let mut hotspots = HotspotMap::load(&deps.storage)?;
+/// Move our window of buckets forward until the newest bucket covers `time`. For each new bucket we
+/// add, the oldest bucket is removed and queued for expiry, which deletes all of its data. Returns
+/// the amount of buckets that were rotated
+pub fn rotate_buckets<S: Storage>(store: &mut S, time: u64) -> StdResult<u64> {
+    let bucket_span = config_read(store).load()?.bucket_span;
+    let mut pointers = Pointers::load(store)?;
 
-    // might be better to create a trie per day, and aggregate it instead of doing it like this?
-    // either way this only happens once per day, so might be acceptable to take a little more time
-    // but still optimize for fast query
-    for (loc, _) in old_bucket.locations.iter() {
-        hotspots.remove_data_point(loc)
+    let newest_end = pointers.last().unwrap().end_time;
+    if newest_end >= time {
+        return Ok(0);
     }
 
-    Ok(HandleResponse::default())
+    // the amount of buckets the window has to move forward. If this is more than the amount of
+    // buckets we have, all of the current buckets expire, and we can skip straight to the end
+    let missing = (time - newest_end + bucket_span - 1) / bucket_span;
+    let expired = std::cmp::min(missing, pointers.len() as u64);
+    let first_start = newest_end + 1 + (missing - expired) * bucket_span;
+
+    // the old buckets are no longer reachable once they are removed from the pointers, so we only
+    // have to queue them here - the data itself is deleted in chunks by `expire_cells`
+    let mut queue = ExpiryQueue::load(store)?;
+    for i in 0..expired {
+        let old_day = pointers.pop_oldest().unwrap();
+        queue.buckets.push(old_day.bucket);
+
+        pointers.insert(Pointer::new(first_start + i * bucket_span, bucket_span));
+    }
+
+    pointers.store(store)?;
+    queue.store(store)?;
+
+    Ok(missing)
+}
+
+/// Change the amount of days for which data is kept. Growing the window adds buckets before the
+/// oldest one, so data is kept for longer from now on. Shrinking it removes the oldest buckets,
+/// and queues all their data for expiry
+pub fn set_retention<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    days: u32,
+) -> StdResult<HandleResponse> {
+    validate_retention(days)?;
+
+    let bucket_span = config_read(&deps.storage).load()?.bucket_span;
+    let mut pointers = Pointers::load(&deps.storage)?;
+    let current = pointers.len() as u32;
+    let target = bucket_count(days, bucket_span);
+
+    let mut queue = ExpiryQueue::load(&deps.storage)?;
+
+    if target > current {
+        let oldest = pointers.first().unwrap().start_time;
+        for i in 1..=(target - current) as u64 {
+            let start_time = oldest.checked_sub(i * bucket_span).ok_or_else(|| {
+                StdError::generic_err("Retention period extends to before the UNIX epoch")
+            })?;
+            let new_day = Pointer::new(start_time, bucket_span);
+
+            // we can't reuse a bucket which still has old data in it
+            if queue.buckets.contains(&new_day.bucket) {
+                return Err(StdError::generic_err(
+                    "Old data for this period is still being expired. Use ProcessExpiry to finish expiring it first",
+                ));
+            }
+            pointers.insert(new_day);
+        }
+    } else if target < current {
+        for _ in target..current {
+            let old_day = pointers.pop_oldest().unwrap();
+            queue.buckets.push(old_day.bucket);
+        }
+    }
+
+    pointers.store(&mut deps.storage)?;
+    queue.store(&mut deps.storage)?;
+
+    let expired = expire_cells(&mut deps.storage, DEFAULT_EXPIRY_LIMIT)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("expired_cells", expired)],
+        data: None,
+    })
 }